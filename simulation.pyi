@@ -1,46 +1,367 @@
 
-from typing import Callable, Dict, Optional
+from typing import Any, Callable, Dict, Hashable, Iterable, List, Optional, Set, Tuple, Union
 import networkx
 
 
-def get_simulation_inter(nx_graph1: networkx.DiGraph, nx_graph2: networkx.DiGraph, is_label_cached=False) -> Dict: 
+class UnknownNodeError(KeyError):
+    """
+    Raised when an edge or a lookup names a node that is not in the graph.
+    """
+
+class CompareFunctionError(Exception):
+    """
+    Raised when a compare function raises or does not return a bool.
+
+    The message names the node pair (or edge pair) that was being compared, and
+    the original exception, with its traceback, is kept as `__cause__`.
+    """
+
+class Graph:
+    """
+    A graph that every simulation function accepts in place of a networkx graph.
+
+    Converting a networkx graph costs a full pass over it, so a data graph that
+    is queried many times should be converted once. Compare functions, label
+    keys and bounds registered on a graph are kept between calls.
+    Those passed to a simulation function apply to that call only; the graph's
+    own settings are restored when it returns. The label cache is kept together
+    with the compare function that built it, and is rebuilt when either graph
+    changes or a different compare function is used.
+    """
+    def __init__(self, directed: bool = True, multigraph: bool = False) -> None: ...
+    @staticmethod
+    def from_networkx(nx_graph: networkx.Graph) -> 'Graph': ...
+    def to_networkx(self) -> networkx.Graph: ...
+    def add_node(self, node: Hashable, **attrs: Any) -> None:
+        """
+        Add a node, or update the attributes of an existing one.
+        """
+    def add_edge(self, u: Hashable, v: Hashable, key: Optional[Hashable] = None, **attrs: Any) -> None:
+        """
+        Add an edge between existing nodes, raising `UnknownNodeError` otherwise.
+
+        On a non-multigraph, adding an existing edge updates its attributes.
+        """
+    def remove_edge(self, u: Hashable, v: Hashable, key: Optional[Hashable] = None) -> None:
+        """
+        Remove an edge, raising `KeyError` if there is none.
+
+        On a multigraph without `key`, the most recently added parallel edge is removed.
+        """
+    def remove_node(self, node: Hashable) -> None:
+        """
+        Remove a node and its edges.
+        """
+    def node_count(self) -> int: ...
+    def edge_count(self) -> int: ...
+    def is_directed(self) -> bool: ...
+    def is_multigraph(self) -> bool: ...
+    def get_node_attributes(self, node: Hashable) -> Optional[Dict]: ...
+    def get_edge_attributes(self, u: Hashable, v: Hashable, key: Optional[Hashable] = None) -> Optional[Dict]: ...
+    def register_compare_fn(self, compare: Callable[[Dict, Dict], bool]) -> None: ...
+    def register_edge_compare_fn(self, compare: Callable[[Dict, Dict], bool]) -> None: ...
+    def register_node_edge_compare_fn(self, compare: Callable[..., bool]) -> None: ...
+    def set_label_keys(self, keys: List[str]) -> None: ...
+    def register_label_key_fn(self, key_fn: Callable[[Dict], Hashable]) -> None: ...
+    def build_compare_cache(self, other: 'Graph') -> int:
+        """
+        Precompute the label-compatible node pairs with `other` and return how many there are.
+        """
+    def __len__(self) -> int: ...
+
+GraphLike = Union[networkx.Graph, Graph]
+
+Pairs = Set[Tuple[Hashable, Hashable]]
+
+class SimulationSession:
+    """
+    The maximum simulation of a query in a data graph, kept up to date while the data graph changes.
+
+    The session works on its own copies of both graphs. Each update changes the data graph the way the
+    matching `Graph` method does, and returns `(added, removed)`: the (query node, data node) pairs that
+    entered and left the relation.
+    """
+    def __init__(self, query: GraphLike, data: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> None: ...
+    def relation(self) -> Dict:
+        """
+        The current relation, in the same form as `get_simulation_inter`.
+        """
+    def insert_edge(self, u: Hashable, v: Hashable, key: Optional[Hashable] = None, **attrs: Any) -> Tuple[Pairs, Pairs]:
+        """
+        Add an edge between existing nodes, raising `UnknownNodeError` otherwise.
+        """
+    def delete_edge(self, u: Hashable, v: Hashable, key: Optional[Hashable] = None) -> Tuple[Pairs, Pairs]: ...
+    def insert_node(self, node: Hashable, **attrs: Any) -> Tuple[Pairs, Pairs]:
+        """
+        Add a node, or update the attributes of an existing one.
+        """
+    def delete_node(self, node: Hashable) -> Tuple[Pairs, Pairs]: ...
+
+
+def get_simulation_inter(nx_graph1: GraphLike, nx_graph2: GraphLike, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict: 
     """
     Get the simulation between two graphs.
+
+    Undirected graphs (`Graph`, `MultiGraph`) are simulated as symmetric directed
+    graphs, so every undirected edge can be followed in both directions.
+
+    The returned dict is keyed by the original nodes of `nx_graph1` and maps each
+    of them to a set of original nodes of `nx_graph2`. If `nx_graph2` is empty,
+    every set is empty.
+
+    Without a compare function, a node of `nx_graph1` is compatible with a node
+    of `nx_graph2` when every one of its attributes is present on the other node
+    with an equal value, compared the way Python's `==` compares them.
+
+    `label_keys` names the attributes that make up a node label, e.g.
+    `["label", "type"]`. They are read once and compared in Rust; a missing
+    attribute counts as `None`. Any compare function only sees node pairs whose
+    labels are equal.
+
+    `label_key_fn` is the general form of `label_keys`: it maps a node attribute
+    dict to a hashable key. It is called once per node, nodes are grouped by key,
+    and only pairs from equal groups are considered. The two options cannot be
+    combined.
     """
 
-def is_simulation_isomorphic(nx_graph1: networkx.DiGraph, nx_graph2: networkx.DiGraph, is_label_cached=False) -> bool:
+def is_simulation_isomorphic(nx_graph1: GraphLike, nx_graph2: GraphLike, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> bool:
     """
     Check if two graphs are isomorphic by graph simulation.
     """
 
-def get_simulation_inter_fn(nx_graph1: networkx.DiGraph, nx_graph2: networkx.DiGraph, compare_fn: Callable, is_label_cached=False) -> Dict: 
+def get_simulation_inter_fn(nx_graph1: GraphLike, nx_graph2: GraphLike, compare_fn: Callable, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict: 
     """
     Get the simulation between two graphs.
     """
     
-def is_simulation_isomorphic_fn(nx_graph1: networkx.DiGraph, nx_graph2: networkx.DiGraph, compare_fn: Callable, is_label_cached=False) -> bool:
+def get_fractional_simulation(nx_graph1: GraphLike, nx_graph2: GraphLike, compare: Callable[[Dict, Dict], float], threshold: float = 0.5, max_iterations: int = 100, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict[Hashable, Dict[Hashable, float]]:
+    """
+    Get the fractional simulation between two graphs.
+
+    The compare function takes two node attribute dictionaries and returns a score in [0, 1]
+    (a bool counts as 0 or 1). Starting from these scores, each round sets
+
+        S(u, v) = compare(u, v) * mean over successors u2 of u of max over successors v2 of v of S(u2, v2)
+
+    where the mean over no successors is 1, until the scores stop changing or max_iterations
+    rounds have run. Pairs whose label_keys or label_key_fn labels differ score 0 without a call.
+
+    Returns a dictionary mapping every node of nx_graph1 to {node of nx_graph2: score} for the
+    pairs whose score is at least threshold. With a bool compare function and threshold 1.0,
+    this is the same relation as get_simulation_inter_fn.
+    """
+
+def is_simulation_isomorphic_fn(nx_graph1: GraphLike, nx_graph2: GraphLike, compare_fn: Callable, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> bool:
     """
     Check if two graphs are isomorphic by graph simulation.
     """
 
-def is_simulation_isomorphic_of_node_edge_fn(nx_graph1: networkx.DiGraph, nx_graph2: networkx.DiGraph, node_compare_fn: Callable,  edge_compare_fn: Callable, is_label_cached=False) -> bool:
+def is_simulation_isomorphic_of_node_edge_fn(nx_graph1: GraphLike, nx_graph2: GraphLike, node_compare_fn: Callable,  edge_compare_fn: Callable, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> bool:
     """
     Check if two graphs are isomorphic by graph simulation.
+
+    Parallel edges of multigraphs are kept with their keys and compared one by one.
     """
 
-def is_simulation_isomorphic_of_edge_fn(nx_graph1: networkx.DiGraph, nx_graph2: networkx.DiGraph, node_edge_compare_fn: Callable, is_label_cached=False) -> bool:
+def get_simulation_of_node_edge_fn(nx_graph1: GraphLike, nx_graph2: GraphLike, node_compare: Callable[[Dict, Dict], bool], edge_compare: Callable[[Dict, Dict], bool], is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict:
+    """
+    Get the simulation between two graphs where matched edges must also have
+    equal labels.
+
+    `node_compare(attrs1, attrs2)` compares nodes and `edge_compare(attrs1, attrs2)`
+    compares edges. The result has the same shape as `get_simulation_inter`.
+    """
+
+def get_simulation_of_edge_fn(nx_graph1: GraphLike, nx_graph2: GraphLike, node_edge_compare: Callable[[Dict, Dict, Dict, Dict, Dict, Dict], bool], is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict:
+    """
+    Get the simulation between two graphs where each query edge is compared as
+    a whole with a data edge.
+
+    `node_edge_compare(src1, edge1, dst1, src2, edge2, dst2)` receives the
+    attributes of both edges and their endpoints. Nodes are not compared on their
+    own, so a query node without outgoing edges matches every data node. With
+    `label_keys` or `label_key_fn`, an edge whose endpoints have different labels
+    is rejected before `node_edge_compare` is called. The result has the same
+    shape as `get_simulation_inter`.
+    """
+
+def is_simulation_isomorphic_of_edge_fn(nx_graph1: GraphLike, nx_graph2: GraphLike, node_edge_compare_fn: Callable, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> bool:
     """
     Check if two graphs are isomorphic by graph simulation.
     """
 
-def get_bounded_simulation(nx_graph1: networkx.DiGraph, nx_graph2: networkx.DiGraph, compare: Callable, bound: Callable, is_label_cached=False) -> Dict:
+def get_dual_simulation(nx_graph1: GraphLike, nx_graph2: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict:
+    """
+    Get the dual simulation between two graphs.
+
+    Besides the child constraints of `get_simulation_inter`, every match of a
+    query node must also have a predecessor matching each of its query parents.
+    Without `compare`, node attributes are compared natively.
+    """
+
+def is_dual_simulation_isomorphic(nx_graph1: GraphLike, nx_graph2: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> bool:
+    """
+    Check if every query node has a match under dual simulation.
+    """
+
+def get_strong_simulation(nx_graph1: GraphLike, nx_graph2: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> List[Dict]:
+    """
+    Get the strong simulation matches of a connected query graph.
+
+    For every data node `w`, dual simulation is computed inside the ball of
+    radius equal to the query diameter around `w` (edge directions ignored).
+    When `w` takes part in the result, the connected part of the match graph
+    containing `w` is reported as one match:
+
+        {"center": w, "relation": {query node: {data nodes}},
+         "nodes": {data nodes}, "edges": [(u, v) or (u, v, key), ...]}
+
+    Matches are listed in the order the centers were added to `nx_graph2`.
+    Raises `ValueError` when the query graph is not connected.
+    """
+
+def batch_simulation(query: GraphLike, data_graphs: Iterable[GraphLike], compare: Optional[Callable[[Dict, Dict], bool]] = None, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None, isomorphic=False) -> List[Union[Dict, bool, Exception]]:
+    """
+    Run one query against many data graphs.
+
+    The query is converted once and the data graphs are simulated in parallel. Calls to
+    compare and label_key_fn still run one at a time, while the graphs are converted.
+
+    Returns one result per data graph, in input order: the relation as returned by
+    get_simulation_inter, or with isomorphic=True whether every query node has a match.
+    A data graph that fails to convert or compare does not make the call raise: its entry in
+    the list is the exception object instead, and the other graphs are not affected. A label
+    cache built on a query Graph is kept.
+    """
+
+def simulation_matrix(graphs: Iterable[GraphLike], compare: Optional[Callable[[Dict, Dict], bool]] = None, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None, score=False) -> List[List[Union[bool, float]]]:
+    """
+    Compare every graph of a collection with every other one.
+
+    Each graph is converted once, and a Graph in the collection is left unchanged. Without
+    compare, each Graph uses the compare function registered on it when it is the query side.
+    Nodes whose attribute dictionaries are equal share a label class, and each compare function
+    is called at most once for each pair of classes that native labels do not already decide.
+    The graph pairs are then simulated in parallel, each with a label table between its own two
+    sets of classes.
+
+    Returns an N x N matrix where entry [i][j] tells whether graphs[i] is simulated by
+    graphs[j], as is_simulation_isomorphic would. With score=True, the entry is instead the
+    share of the nodes of graphs[i] that have a match in graphs[j].
+    """
+
+def bisimulation_classes(graph: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict[Hashable, int]:
+    """
+    Get the classes of the maximal bisimulation of a graph with itself.
+
+    Two nodes start in the same class when their labels match: with compare, when it returns True
+    in both directions; with label_keys or label_key_fn, when their labels are equal; otherwise
+    when their attributes are equal. Classes are then split until the nodes of a class reach the
+    same classes through their successors.
+
+    Returns a dictionary mapping every node to its class id. Ids count up from 0 in node order.
+    """
+
+def quotient_graph(graph: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Graph:
+    """
+    Compress a graph by merging the nodes of each bisimulation class.
+
+    Each class of bisimulation_classes becomes one node, keyed by its first node and carrying
+    that node's attributes. Two classes are joined by one edge, without attributes, whenever an
+    edge joins their members. A data node matches a query node exactly when the node standing
+    for its class does in the quotient.
+    """
+
+def minimize_query(query: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None, return_mapping: bool = False) -> Union[Graph, Tuple[Graph, Dict[Hashable, Hashable]]]:
+    """
+    Shrink a query without changing its simulation in any data graph.
+
+    Query nodes that simulate each other always have the same matches, so each such class is
+    merged into its first node, which keeps its key and attributes. An edge (u, u2) is dropped
+    when u has another edge (u, u3) and u3 simulates u2, since every match of u3 also matches
+    u2. The edges of the result carry no attributes.
+
+    A data node matches an original query node exactly when it matches the node standing for
+    it. This needs compare to be transitive, like equality: if a is compatible with b and b
+    with c, then a is compatible with c. The default value-based matching and label_keys or
+    label_key_fn grouping are. With a compare such as abs(a["w"] - b["w"]) <= 1, merged nodes
+    can match different data nodes. With return_mapping=True, returns
+    (graph, {query node: node standing for it}).
+    """
+
+def query_contained(q1: GraphLike, q2: GraphLike, compare: Optional[Callable[[Dict, Dict], bool]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Tuple[bool, Optional[Dict[Hashable, Set[Hashable]]]]:
+    """
+    Decide whether pattern q1 is contained in pattern q2 under simulation.
+
+    The witness is get_simulation_inter(q2, q1): every q2 node is mapped to the q1 nodes that
+    simulate it. q1 is contained in q2 when
+    - every q2 node is simulated by some q1 node,
+    - every q1 node simulates some q2 node, and
+    - every q1 edge (a, b) has a q2 edge (x, y) with a simulating x and b simulating y.
+    Then on any data graph where q1 has a match for every node, so does q2, each q1 node a
+    only matches data nodes that every q2 node it simulates also matches, and every data edge
+    q1 matches is matched by some q2 edge. This relies on compare being transitive, like
+    equality is; the default value-based matching and label_keys/label_key_fn grouping are.
+    An empty q1 matches nothing and is contained in every pattern.
+
+    Returns (True, {q2 node: set of q1 nodes simulating it}) if q1 is contained in q2, and
+    (False, None) otherwise. compare is called as compare(q2 attrs, q1 attrs).
+    """
+
+def explain_simulation(nx_graph1: GraphLike, nx_graph2: GraphLike, node: Hashable, data_node: Optional[Hashable] = None, compare: Optional[Callable[[Dict, Dict], bool]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Union[List[Dict], Dict[Hashable, List[Dict]]]:
+    """
+    Explain why a pair is not in the simulation relation.
+
+    With data_node, returns the chain of removals that took (node, data_node) out of the relation,
+    or an empty list if the pair is in it. Each step is one of
+
+        {"pair": (u, v), "reason": "label"}
+        {"pair": (u, v), "reason": "successor", "edge": (u, u2), "removed": (u2, w) or None}
+
+    A "successor" step means v was left without a successor matching u2. "removed" is the last
+    pair (u2, w) whose removal caused this, and the next step explains it. None means v never had
+    a successor compatible with u2, which ends the chain.
+
+    Without data_node, returns a dictionary mapping every label-compatible candidate of node that
+    was removed to its chain. An empty dictionary for a node with no matches means it had no
+    label-compatible candidates at all.
+    """
+
+def get_top_k_simulation(nx_graph1: GraphLike, nx_graph2: GraphLike, k: int, score: Union[None, str, Callable[[Dict, Dict], float]] = None, compare: Optional[Callable[[Dict, Dict], bool]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None) -> Dict[Hashable, List[Tuple[Hashable, float]]]:
+    """
+    Get the k highest scored matches of every node of nx_graph1 in the simulation relation.
+
+    score ranks the label-compatible candidates and may be:
+    - "neighborhood" (the default): the share of the candidate's neighbours that are
+      compatible with a neighbour of the query node in the same direction,
+    - "degree": the smaller total degree of the pair divided by the larger one,
+    - a function taking the query and data node attribute dictionaries and returning a float.
+
+    Candidates are checked against the simulation in descending score order, and each query
+    node stops once k of them are confirmed. A score function is only called for candidates
+    not already ruled out, and raises CompareFunctionError if it fails or returns a non-number.
+    Returns a dictionary mapping every node of nx_graph1 to a list of (data node, score),
+    best first, with ties in nx_graph2 order.
+    """
+
+def get_bounded_simulation(nx_graph1: GraphLike, nx_graph2: GraphLike, compare: Callable, bound: Optional[Callable[[Dict], Union[int, str]]] = None, is_label_cached=False, label_keys: Optional[List[str]] = None, label_key_fn: Optional[Callable[[Dict], Hashable]] = None, edge_bound: Union[None, int, str, Callable[[Dict, Dict, Dict], Union[int, str]]] = None, return_witnesses=False) -> Union[Dict, Tuple[Dict, Dict]]:
     """
     Get the bounded simulation between two graphs.
     
     The compare function should take two node attribute dictionaries and return True if they have the same label.
-    The bound function should take a node attribute dictionary and return an integer representing the bound value for that node.
+    bound takes a node attribute dictionary and gives the bound of every edge leaving that node, as an int or "*" for
+    paths of any length. A call that fails or returns anything else gives 0, as do nodes when bound is omitted.
+    edge_bound gives the bound of each edge and overrides bound. It may be:
+    - the name of an edge attribute holding the bound; edges without it keep the bound of their source node,
+    - a function taking the source, edge and target attribute dictionaries,
+    - an int or "*" used for every edge.
+    Edge bounds that are not ints or "*" are 0.
     
     Returns a dictionary mapping nodes from nx_graph1 to sets of nodes from nx_graph2 that satisfy the bounded simulation.
+    
+    With return_witnesses=True, returns (relation, witnesses) instead. witnesses maps every query edge (u, u') to a
+    dictionary from each matched pair (v, v') with v' reachable from v within the bound to a shortest such path in
+    nx_graph2, given as a list of nodes from v to v' ([v] when v' is v). witnesses is empty when the relation is.
     """
 
 class Node:
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// Classes of the maximum bisimulation of a graph with itself.
+///
+/// `initial` gives every node the class of its label. Classes are split until
+/// the nodes of a class reach the same set of classes through their
+/// successors. Class ids are numbered in the order their first node appears.
+pub fn bisimulation_classes(graph: &AdjacencyIndex, initial: Vec<usize>) -> Vec<usize> {
+    let mut classes = renumber(initial.iter().map(|&class| (class, Vec::new())));
+    let mut count = classes.iter().max().map_or(0, |&class| class + 1);
+    loop {
+        let signatures = (0..graph.node_count()).map(|v| {
+            let mut next: Vec<usize> = graph.successors(v).iter().map(|&w| classes[w]).collect();
+            next.sort_unstable();
+            next.dedup();
+            (classes[v], next)
+        });
+        let refined = renumber(signatures);
+        // 类只会分裂，个数不变时已经稳定
+        let refined_count = refined.iter().max().map_or(0, |&class| class + 1);
+        if refined_count == count {
+            return refined;
+        }
+        classes = refined;
+        count = refined_count;
+    }
+}
+
+// 相同的签名得到相同的编号，按第一次出现的顺序从 0 开始
+fn renumber(signatures: impl Iterator<Item = (usize, Vec<usize>)>) -> Vec<usize> {
+    let mut ids: HashMap<(usize, Vec<usize>), usize> = HashMap::new();
+    signatures
+        .map(|signature| {
+            let next = ids.len();
+            *ids.entry(signature).or_insert(next)
+        })
+        .collect()
+}
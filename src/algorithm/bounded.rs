@@ -0,0 +1,59 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rayon::prelude::*;
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// Bound of a query edge that any path length satisfies (`"*"` in Python).
+pub const UNBOUNDED: usize = usize::MAX;
+
+/// Shortest witness paths of a bounded simulation.
+///
+/// For every query edge `(u, u', k)` and every `v` in `sim(u)`, `v'` in
+/// `sim(u')` with `v'` reachable from `v` within `k` edges, yields
+/// `(edge index, path)` where `path` starts at `v`, ends at `v'` and is a
+/// shortest path between them (just `[v]` when `v'` is `v`).
+pub fn witnesses(edges: &[(usize, usize, usize)], data: &AdjacencyIndex, sim: &[HashSet<usize>]) -> Vec<(usize, Vec<usize>)> {
+    let sources: Vec<(usize, usize)> = edges.iter().enumerate()
+        .flat_map(|(e, &(u, _, _))| sim[u].iter().map(move |&v| (e, v)))
+        .collect();
+    sources.into_par_iter()
+        .flat_map_iter(|(e, v)| {
+            let (_, u_next, bound) = edges[e];
+            shortest_paths(data, v, bound, &sim[u_next]).into_iter().map(move |path| (e, path))
+        })
+        .collect()
+}
+
+// 从 source 出发做有界 BFS，记录父节点后还原到 targets 中每个节点的最短路径
+fn shortest_paths(data: &AdjacencyIndex, source: usize, bound: usize, targets: &HashSet<usize>) -> Vec<Vec<usize>> {
+    let mut parents = HashMap::from([(source, (source, 0))]);
+    let mut queue = VecDeque::from([source]);
+    while let Some(node) = queue.pop_front() {
+        let distance = parents[&node].1;
+        if distance == bound {
+            continue;
+        }
+        for &next in data.successors(node) {
+            if let Entry::Vacant(entry) = parents.entry(next) {
+                entry.insert((node, distance + 1));
+                queue.push_back(next);
+            }
+        }
+    }
+
+    targets.iter()
+        .filter(|target| parents.contains_key(target))
+        .map(|&target| {
+            let mut path = vec![target];
+            let mut node = target;
+            while node != source {
+                node = parents[&node].0;
+                path.push(node);
+            }
+            path.reverse();
+            path
+        })
+        .collect()
+}
@@ -0,0 +1,39 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// Dual simulation: a match of `u` must keep the children of `u` through its
+/// successors and the parents of `u` through its predecessors.
+///
+/// Shrinks `sim` (indexed by query node) to the largest dual simulation
+/// contained in it. Data neighbours outside every candidate set never count
+/// as support, so restricting the candidates to a subgraph restricts the
+/// result to it too.
+pub fn dual_refine(query: &AdjacencyIndex, data: &AdjacencyIndex, mut sim: Vec<HashSet<usize>>) -> Vec<HashSet<usize>> {
+    let mut queue: VecDeque<usize> = (0..query.node_count()).collect();
+    let mut queued = vec![true; query.node_count()];
+    while let Some(u) = queue.pop_front() {
+        queued[u] = false;
+        // sim(u) 变小后，u 的前驱失去后继约束的支撑，u 的后继失去前驱约束的支撑
+        let sim_u = sim[u].clone();
+
+        for &u_pre in query.predecessors(u) {
+            let before = sim[u_pre].len();
+            sim[u_pre].retain(|&v| data.successors(v).iter().any(|w| sim_u.contains(w)));
+            if sim[u_pre].len() != before && !queued[u_pre] {
+                queued[u_pre] = true;
+                queue.push_back(u_pre);
+            }
+        }
+
+        for &u_post in query.successors(u) {
+            let before = sim[u_post].len();
+            sim[u_post].retain(|&v| data.predecessors(v).iter().any(|w| sim_u.contains(w)));
+            if sim[u_post].len() != before && !queued[u_post] {
+                queued[u_post] = true;
+                queue.push_back(u_post);
+            }
+        }
+    }
+    sim
+}
@@ -0,0 +1,68 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// Why a label-compatible pair `(u, v)` left the simulation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Removal {
+    /// The query successor `u'` of `u` that `v` could no longer match.
+    pub query_next: usize,
+    /// The last data successor `w` of `v` whose pair `(u', w)` was removed,
+    /// or `None` if `v` never had a successor compatible with `u'`.
+    pub cause: Option<usize>,
+}
+
+/// Removed pairs and why they were removed.
+pub type Removals = HashMap<(usize, usize), Removal>;
+
+/// Maximum simulation that records the reason for every removed pair.
+///
+/// Following `cause` from a removal gives the chain of removals that led to
+/// it; the chain ends at a pair whose data node never had a compatible
+/// successor.
+pub fn explained_simulation(query: &AdjacencyIndex, data: &AdjacencyIndex, mut sim: Vec<HashSet<usize>>) -> (Vec<HashSet<usize>>, Removals) {
+    let edges: Vec<(usize, usize)> = (0..query.node_count())
+        .flat_map(|u| query.successors(u).iter().map(move |&u_next| (u, u_next)))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let distinct = |nodes: &[usize]| nodes.iter().copied().collect::<HashSet<usize>>();
+
+    // counts[e][v]：查询边 e = (u, u') 下 v 在 sim(u') 中的不同后继个数
+    let mut counts: Vec<HashMap<usize, usize>> = edges.iter()
+        .map(|&(u, u_next)| {
+            sim[u].iter()
+                .map(|&v| (v, distinct(data.successors(v)).iter().filter(|w| sim[u_next].contains(w)).count()))
+                .collect()
+        })
+        .collect();
+
+    let mut removals = Removals::new();
+    let mut removed = VecDeque::new();
+    for (e, &(u, u_next)) in edges.iter().enumerate() {
+        for (&v, _) in counts[e].iter().filter(|(_, &count)| count == 0) {
+            if sim[u].remove(&v) {
+                removals.insert((u, v), Removal { query_next: u_next, cause: None });
+                removed.push_back((u, v));
+            }
+        }
+    }
+
+    while let Some((u_next, w)) = removed.pop_front() {
+        for (e, &(u, _)) in edges.iter().enumerate().filter(|(_, edge)| edge.1 == u_next) {
+            for x in distinct(data.predecessors(w)) {
+                if !sim[u].contains(&x) {
+                    continue;
+                }
+                let count = counts[e].get_mut(&x).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    sim[u].remove(&x);
+                    removals.insert((u, x), Removal { query_next: u_next, cause: Some(w) });
+                    removed.push_back((u, x));
+                }
+            }
+        }
+    }
+    (sim, removals)
+}
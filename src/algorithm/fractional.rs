@@ -0,0 +1,61 @@
+use std::collections::{BTreeSet, HashMap};
+
+use rayon::prelude::*;
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// Fractional simulation scores.
+///
+/// `label[u]` maps the data nodes `v` with a nonzero label score to that
+/// score in `[0, 1]`. Starting from the label scores, every round sets
+///
+/// ```text
+/// S(u, v) = label(u, v) * mean over u' in succ(u) of max over v' in succ(v) of S(u', v')
+/// ```
+///
+/// where the mean over no successors is 1. Scores never increase, so the
+/// rounds stop once no score changes by more than `tolerance`, or after
+/// `max_iterations` rounds. With scores of 0 and 1 only, the pairs that keep
+/// score 1 are exactly the maximum simulation.
+pub fn fractional_simulation(
+    query: &AdjacencyIndex,
+    data: &AdjacencyIndex,
+    label: &[HashMap<usize, f64>],
+    tolerance: f64,
+    max_iterations: usize,
+) -> Vec<HashMap<usize, f64>> {
+    let successors: Vec<BTreeSet<usize>> = (0..query.node_count())
+        .map(|u| query.successors(u).iter().copied().collect())
+        .collect();
+    let mut scores = label.to_vec();
+    for _ in 0..max_iterations {
+        let next: Vec<HashMap<usize, f64>> = (0..label.len())
+            .into_par_iter()
+            .map(|u| {
+                // 分数不会增加，已经是 0 的节点对不用再算
+                scores[u].keys()
+                    .map(|&v| {
+                        let support = successors[u].iter()
+                            .map(|&u_next| {
+                                data.successors(v).iter()
+                                    .filter_map(|v_next| scores[u_next].get(v_next))
+                                    .fold(0.0, |best: f64, &score| best.max(score))
+                            })
+                            .sum::<f64>();
+                        let support = if successors[u].is_empty() { 1.0 } else { support / successors[u].len() as f64 };
+                        (v, label[u][&v] * support)
+                    })
+                    .filter(|&(_, score)| score > 0.0)
+                    .collect()
+            })
+            .collect();
+        let change = next.iter().zip(&scores)
+            .flat_map(|(next_u, scores_u)| scores_u.iter().map(|(v, &score)| score - next_u.get(v).copied().unwrap_or(0.0)))
+            .fold(0.0, f64::max);
+        scores = next;
+        if change <= tolerance {
+            break;
+        }
+    }
+    scores
+}
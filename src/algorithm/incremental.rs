@@ -0,0 +1,69 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// Restores the simulation property of `sim` after the data graph changed.
+///
+/// The pairs in `dropped` are removed unconditionally, the pairs in `pending`
+/// only if some query edge `(u, u')` no longer has a successor of `v` in
+/// `sim(u')`. Every removal re-checks the pairs it was supporting, so only the
+/// pairs whose support may have changed need to be given. Returns the removed
+/// pairs.
+pub fn refine_simulation(
+    query: &AdjacencyIndex,
+    data: &AdjacencyIndex,
+    sim: &mut [HashSet<usize>],
+    dropped: Vec<(usize, usize)>,
+    pending: Vec<(usize, usize)>,
+) -> Vec<(usize, usize)> {
+    let mut queue: VecDeque<(usize, usize, bool)> = dropped.into_iter().map(|(u, v)| (u, v, true))
+        .chain(pending.into_iter().map(|(u, v)| (u, v, false)))
+        .collect();
+    let mut removed = Vec::new();
+    while let Some((u, v, forced)) = queue.pop_front() {
+        if !sim[u].contains(&v) {
+            continue;
+        }
+        if !forced && query.successors(u).iter().all(|&u_next| data.successors(v).iter().any(|w| sim[u_next].contains(w))) {
+            continue;
+        }
+        sim[u].remove(&v);
+        removed.push((u, v));
+        // (u, v) 可能是 (u_prev, x) 在边 (u_prev, u) 上的唯一支撑
+        for &u_prev in query.predecessors(u) {
+            for &x in data.predecessors(v) {
+                if sim[u_prev].contains(&x) {
+                    queue.push_back((u_prev, x, false));
+                }
+            }
+        }
+    }
+    removed
+}
+
+/// Maximum simulation inside the label-compatible `candidates`.
+pub fn maximum_simulation(query: &AdjacencyIndex, data: &AdjacencyIndex, mut candidates: Vec<HashSet<usize>>) -> Vec<HashSet<usize>> {
+    let pending = candidates.iter().enumerate()
+        .flat_map(|(u, sim_u)| sim_u.iter().map(move |&v| (u, v)))
+        .collect();
+    refine_simulation(query, data, &mut candidates, Vec::new(), pending);
+    candidates
+}
+
+/// Nodes that can reach one of `nodes`, including `nodes` themselves.
+///
+/// A pair `(u, v)` can only enter the maximum simulation after a change at
+/// `node` if `v` reaches `node`: everything reachable from other nodes is
+/// unchanged.
+pub fn ancestors(data: &AdjacencyIndex, nodes: &[usize]) -> HashSet<usize> {
+    let mut seen: HashSet<usize> = nodes.iter().copied().collect();
+    let mut queue: VecDeque<usize> = seen.iter().copied().collect();
+    while let Some(node) = queue.pop_front() {
+        for &prev in data.predecessors(node) {
+            if seen.insert(prev) {
+                queue.push_back(prev);
+            }
+        }
+    }
+    seen
+}
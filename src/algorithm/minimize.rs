@@ -0,0 +1,48 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// A query with simulation-equivalent nodes merged.
+pub struct Minimized {
+    /// The class of every query node, numbered in the order its first node appears.
+    pub classes: Vec<usize>,
+    /// The first node of every class.
+    pub representatives: Vec<usize>,
+    /// The edges kept between classes.
+    pub arcs: BTreeSet<(usize, usize)>,
+}
+
+/// Minimizes a query using its maximum simulation `self_sim` with itself.
+///
+/// Nodes that simulate each other have the same matches in every data graph,
+/// so each class of them becomes one node. An edge `(c, d)` is then dropped
+/// when `c` has another edge `(c, e)` with `e` simulating `d`: every match of
+/// `e` is a match of `d`, so the other edge already implies it.
+///
+/// Both steps compose simulations, so they assume label compatibility is
+/// transitive.
+pub fn minimize(query: &AdjacencyIndex, self_sim: &[HashSet<usize>]) -> Minimized {
+    let mut classes = Vec::with_capacity(query.node_count());
+    let mut representatives: Vec<usize> = Vec::new();
+    for u in 0..query.node_count() {
+        let class = representatives.iter()
+            .position(|&r| self_sim[u].contains(&r) && self_sim[r].contains(&u))
+            .unwrap_or_else(|| {
+                representatives.push(u);
+                representatives.len() - 1
+            });
+        classes.push(class);
+    }
+
+    let all: BTreeSet<(usize, usize)> = (0..query.node_count())
+        .flat_map(|u| query.successors(u).iter().map(move |&w| (u, w)))
+        .map(|(u, w)| (classes[u], classes[w]))
+        .collect();
+    // e 模拟 d 时 e 的匹配都是 d 的匹配
+    let simulates = |e: usize, d: usize| self_sim[representatives[d]].contains(&representatives[e]);
+    let arcs = all.iter()
+        .filter(|&&(c, d)| !all.range((c, 0)..(c + 1, 0)).any(|&(_, e)| e != d && simulates(e, d)))
+        .copied()
+        .collect();
+    Minimized { classes, representatives, arcs }
+}
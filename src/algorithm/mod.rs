@@ -0,0 +1,9 @@
+pub mod dual;
+pub mod strong;
+pub mod bounded;
+pub mod incremental;
+pub mod topk;
+pub mod explain;
+pub mod fractional;
+pub mod bisimulation;
+pub mod minimize;
@@ -0,0 +1,110 @@
+use rayon::prelude::*;
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use super::dual::dual_refine;
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// A match of strong simulation inside the ball around `center`.
+pub struct StrongMatch {
+    pub center: usize,
+    /// Data nodes matched by each query node, indexed by query node.
+    pub relation: Vec<HashSet<usize>>,
+    /// Data edges that realise a query edge, as indices into the edge list.
+    pub edges: BTreeSet<usize>,
+}
+
+/// Largest distance between two nodes with edge directions ignored, or `None`
+/// when the graph is not connected.
+pub fn diameter(graph: &AdjacencyIndex) -> Option<usize> {
+    let mut diameter = 0;
+    for source in 0..graph.node_count() {
+        let distances = ball(graph, source, usize::MAX);
+        if distances.len() != graph.node_count() {
+            return None;
+        }
+        diameter = diameter.max(distances.into_values().max().unwrap_or(0));
+    }
+    Some(diameter)
+}
+
+/// Strong simulation: dual simulation restricted to every ball whose radius is
+/// the query diameter, keeping the connected part of the match around the
+/// ball center. Returns one match per center in ascending order, or `None`
+/// when the query is not connected.
+pub fn strong_simulation(query: &AdjacencyIndex, data: &AdjacencyIndex, candidates: Vec<HashSet<usize>>) -> Option<Vec<StrongMatch>> {
+    let radius = diameter(query)?;
+    // 球内的 dual simulation 一定包含在全图的 dual simulation 中
+    let global = dual_refine(query, data, candidates);
+    if global.iter().any(HashSet::is_empty) {
+        return Some(Vec::new());
+    }
+    let centers: Vec<usize> = global.iter().flatten().copied().collect::<BTreeSet<_>>().into_iter().collect();
+    Some(centers.into_par_iter()
+        .filter_map(|center| match_in_ball(query, data, &global, center, radius))
+        .collect())
+}
+
+fn match_in_ball(query: &AdjacencyIndex, data: &AdjacencyIndex, global: &[HashSet<usize>], center: usize, radius: usize) -> Option<StrongMatch> {
+    let ball = ball(data, center, radius);
+    let candidates = global.iter()
+        .map(|sim_u| sim_u.iter().copied().filter(|v| ball.contains_key(v)).collect())
+        .collect();
+    let mut relation = dual_refine(query, data, candidates);
+    if relation.iter().any(HashSet::is_empty) || !relation.iter().any(|sim_u| sim_u.contains(&center)) {
+        return None;
+    }
+
+    // 匹配图：能对应到某条查询边 (u, u') 的数据边 (v, v')
+    let mut edges = BTreeSet::new();
+    for u in 0..query.node_count() {
+        for &u_next in query.successors(u) {
+            for &v in &relation[u] {
+                let out = data.out_edges(v).iter().zip(data.successors(v));
+                edges.extend(out.filter(|(_, v_next)| relation[u_next].contains(v_next)).map(|(&edge, _)| edge));
+            }
+        }
+    }
+
+    // 只保留匹配图中包含中心的连通分量
+    let mut neighbours: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &edge in &edges {
+        let (v, v_next) = data.edge_ends(edge);
+        neighbours.entry(v).or_default().push(v_next);
+        neighbours.entry(v_next).or_default().push(v);
+    }
+    let mut component = HashSet::from([center]);
+    let mut queue = VecDeque::from([center]);
+    while let Some(v) = queue.pop_front() {
+        for &w in neighbours.get(&v).into_iter().flatten() {
+            if component.insert(w) {
+                queue.push_back(w);
+            }
+        }
+    }
+    for sim_u in &mut relation {
+        sim_u.retain(|v| component.contains(v));
+    }
+    edges.retain(|&edge| component.contains(&data.edge_ends(edge).0));
+
+    Some(StrongMatch { center, relation, edges })
+}
+
+// 忽略方向时与 source 距离不超过 radius 的节点及其距离
+fn ball(graph: &AdjacencyIndex, source: usize, radius: usize) -> HashMap<usize, usize> {
+    let mut distances = HashMap::from([(source, 0)]);
+    let mut queue = VecDeque::from([source]);
+    while let Some(v) = queue.pop_front() {
+        let distance = distances[&v];
+        if distance == radius {
+            continue;
+        }
+        for &w in graph.successors(v).iter().chain(graph.predecessors(v)) {
+            distances.entry(w).or_insert_with(|| {
+                queue.push_back(w);
+                distance + 1
+            });
+        }
+    }
+    distances
+}
@@ -0,0 +1,156 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::graph::adjacency::AdjacencyIndex;
+
+/// The `k` highest scored data nodes of every query node that are in the
+/// maximum simulation.
+///
+/// `score(u, v)` scores a label-compatible candidate `v` of `u`. Query nodes
+/// are handled one at a time: the candidates of `u` not already ruled out are
+/// scored, then confirmed one at a time in descending score order (ties go to
+/// the lower node index) until `k` of them are confirmed, so the relation is
+/// only computed around the candidates that were looked at. The first error
+/// from `score` stops the search.
+pub fn top_k<E>(
+    query: &AdjacencyIndex,
+    data: &AdjacencyIndex,
+    candidates: &[HashSet<usize>],
+    k: usize,
+    mut score: impl FnMut(usize, usize) -> Result<f64, E>,
+) -> Result<Vec<Vec<(usize, f64)>>, E> {
+    let mut confirmer = Confirmer { query, data, candidates, known: HashMap::new() };
+    let mut ranked = Vec::with_capacity(candidates.len());
+    for (u, candidates_u) in candidates.iter().enumerate() {
+        let mut matches = Vec::new();
+        if k > 0 {
+            // 确认其他查询节点时已经排除的候选不再打分
+            let mut heap = BinaryHeap::new();
+            for &node in candidates_u.iter().filter(|&&node| confirmer.known.get(&(u, node)) != Some(&false)) {
+                heap.push(Ranked { score: score(u, node)?, node });
+            }
+            while matches.len() < k {
+                let Some(Ranked { score, node }) = heap.pop() else { break };
+                if confirmer.confirm(u, node) {
+                    matches.push((node, score));
+                }
+            }
+        }
+        ranked.push(matches);
+    }
+    Ok(ranked)
+}
+
+/// Share of the neighbours of `v` that are label-compatible with a neighbour
+/// of `u` in the same direction, or 1 if `v` has no neighbours.
+pub fn neighborhood_score(query: &AdjacencyIndex, data: &AdjacencyIndex, candidates: &[HashSet<usize>], u: usize, v: usize) -> f64 {
+    let forward = data.successors(v).iter().map(|&w| (w, query.successors(u)));
+    let backward = data.predecessors(v).iter().map(|&w| (w, query.predecessors(u)));
+    let mut neighbours: HashMap<usize, bool> = HashMap::new();
+    for (w, query_side) in forward.chain(backward) {
+        let matched = query_side.iter().any(|&u_next| candidates[u_next].contains(&w));
+        *neighbours.entry(w).or_default() |= matched;
+    }
+    if neighbours.is_empty() {
+        return 1.0;
+    }
+    neighbours.values().filter(|&&matched| matched).count() as f64 / neighbours.len() as f64
+}
+
+/// Ratio of the smaller to the larger total degree of `u` and `v`.
+pub fn degree_score(query: &AdjacencyIndex, data: &AdjacencyIndex, u: usize, v: usize) -> f64 {
+    let degree_u = query.out_degree(u) + query.in_degree(u);
+    let degree_v = data.out_degree(v) + data.in_degree(v);
+    if degree_u.max(degree_v) == 0 {
+        return 1.0;
+    }
+    degree_u.min(degree_v) as f64 / degree_u.max(degree_v) as f64
+}
+
+struct Ranked {
+    score: f64,
+    node: usize,
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// 分数高的先出堆，分数相同时下标小的先出堆
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.total_cmp(&other.score).then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+// 按需判断节点对是否属于最大模拟，结果在多次调用之间共享
+struct Confirmer<'a> {
+    query: &'a AdjacencyIndex,
+    data: &'a AdjacencyIndex,
+    candidates: &'a [HashSet<usize>],
+    known: HashMap<(usize, usize), bool>,
+}
+
+impl Confirmer<'_> {
+    fn confirm(&mut self, u: usize, v: usize) -> bool {
+        if let Some(&known) = self.known.get(&(u, v)) {
+            return known;
+        }
+
+        // 从 (u, v) 出发收集它依赖的全部节点对；已知的节点对不再展开
+        let mut region = vec![(u, v)];
+        let mut index = HashMap::from([((u, v), 0)]);
+        let mut parents: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut next = 0;
+        while next < region.len() {
+            let (a, b) = region[next];
+            if !self.known.contains_key(&(a, b)) {
+                for &a_next in self.query.successors(a) {
+                    for &b_next in self.data.successors(b) {
+                        if !self.candidates[a_next].contains(&b_next) || self.known.get(&(a_next, b_next)) == Some(&false) {
+                            continue;
+                        }
+                        let child = *index.entry((a_next, b_next)).or_insert_with(|| {
+                            region.push((a_next, b_next));
+                            parents.push(Vec::new());
+                            region.len() - 1
+                        });
+                        parents[child].push(next);
+                    }
+                }
+            }
+            next += 1;
+        }
+
+        // 区域对依赖封闭，区域内的最大不动点就是这些节点对在整个最大模拟中的结果
+        let mut alive = vec![true; region.len()];
+        let mut queue: VecDeque<usize> = (0..region.len()).collect();
+        while let Some(i) = queue.pop_front() {
+            let (a, b) = region[i];
+            if !alive[i] || self.known.contains_key(&(a, b)) {
+                continue;
+            }
+            let supported = self.query.successors(a).iter().all(|&a_next| {
+                self.data.successors(b).iter().any(|&b_next| index.get(&(a_next, b_next)).is_some_and(|&j| alive[j]))
+            });
+            if !supported {
+                alive[i] = false;
+                queue.extend(parents[i].iter().copied());
+            }
+        }
+        for (pair, alive) in region.into_iter().zip(alive) {
+            self.known.entry(pair).or_insert(alive);
+        }
+        self.known[&(u, v)]
+    }
+}
@@ -0,0 +1,80 @@
+/// Forward and reverse adjacency of a graph in compressed sparse row form.
+///
+/// The out-edges of node `i` are `out_edges[out_offsets[i]..out_offsets[i + 1]]`,
+/// stored as indices into the graph's edge list next to the node they lead to.
+/// In-edges are laid out the same way.
+#[derive(Clone, Debug, Default)]
+pub struct AdjacencyIndex {
+    out_offsets: Vec<usize>,
+    out_edges: Vec<usize>,
+    out_targets: Vec<usize>,
+    in_offsets: Vec<usize>,
+    in_edges: Vec<usize>,
+    in_sources: Vec<usize>,
+    ends: Vec<(usize, usize)>,
+}
+
+impl AdjacencyIndex {
+    /// Builds the index from `(source, target)` pairs given in edge order.
+    pub fn new(node_count: usize, pairs: &[(usize, usize)]) -> Self {
+        let (out_offsets, out_edges) = bucket(node_count, pairs.iter().map(|&(source, _)| source));
+        let (in_offsets, in_edges) = bucket(node_count, pairs.iter().map(|&(_, target)| target));
+        let out_targets = out_edges.iter().map(|&edge| pairs[edge].1).collect();
+        let in_sources = in_edges.iter().map(|&edge| pairs[edge].0).collect();
+        let ends = pairs.to_vec();
+        AdjacencyIndex { out_offsets, out_edges, out_targets, in_offsets, in_edges, in_sources, ends }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.out_offsets.len().saturating_sub(1)
+    }
+
+    pub fn successors(&self, node: usize) -> &[usize] {
+        &self.out_targets[self.out_offsets[node]..self.out_offsets[node + 1]]
+    }
+
+    pub fn predecessors(&self, node: usize) -> &[usize] {
+        &self.in_sources[self.in_offsets[node]..self.in_offsets[node + 1]]
+    }
+
+    /// Edge indices leaving `node`, in the same order as [`Self::successors`].
+    pub fn out_edges(&self, node: usize) -> &[usize] {
+        &self.out_edges[self.out_offsets[node]..self.out_offsets[node + 1]]
+    }
+
+    /// Edge indices entering `node`, in the same order as [`Self::predecessors`].
+    pub fn in_edges(&self, node: usize) -> &[usize] {
+        &self.in_edges[self.in_offsets[node]..self.in_offsets[node + 1]]
+    }
+
+    /// `(source, target)` of an edge.
+    pub fn edge_ends(&self, edge: usize) -> (usize, usize) {
+        self.ends[edge]
+    }
+
+    pub fn out_degree(&self, node: usize) -> usize {
+        self.out_offsets[node + 1] - self.out_offsets[node]
+    }
+
+    pub fn in_degree(&self, node: usize) -> usize {
+        self.in_offsets[node + 1] - self.in_offsets[node]
+    }
+}
+
+// 计数排序：按端点把边下标分桶，桶内保持加边顺序
+fn bucket(node_count: usize, ends: impl Iterator<Item = usize> + Clone) -> (Vec<usize>, Vec<usize>) {
+    let mut offsets = vec![0; node_count + 1];
+    for end in ends.clone() {
+        offsets[end + 1] += 1;
+    }
+    for i in 0..node_count {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut next = offsets.clone();
+    let mut edges = vec![0; offsets[node_count]];
+    for (edge, end) in ends.enumerate() {
+        edges[next[end]] = edge;
+        next[end] += 1;
+    }
+    (offsets, edges)
+}
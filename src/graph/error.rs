@@ -0,0 +1,16 @@
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyKeyError};
+
+create_exception!(
+    simulation,
+    UnknownNodeError,
+    PyKeyError,
+    "Raised when an edge or a lookup names a node that is not in the graph."
+);
+
+create_exception!(
+    simulation,
+    CompareFunctionError,
+    PyException,
+    "Raised when a compare function raises or does not return a bool. The original error is its `__cause__`."
+);
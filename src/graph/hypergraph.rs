@@ -1,8 +1,8 @@
 use std::{collections::{HashMap, HashSet}, fmt::Display, hash::Hash};
 
-use pyo3::{prelude::*, types::PyList};
+use pyo3::prelude::*;
 use graph_simulation::{algorithm::hyper_simulation::{DMatch, Delta, HSEvent, HyperSimulation, HyperSimulationTrace, LMatch, LPredicate, SematicCluster}, utils::logger::TraceLog};
-use graph_base::interfaces::{edge, graph::{self, SingleId}, hypergraph::{self, ContainedHyperedge}, typed, vertex};
+use graph_base::interfaces::{edge, graph, hypergraph::{self, ContainedHyperedge}, typed, vertex};
 
 // use graph_base::interfaces::hypergraph;
 
@@ -106,6 +106,12 @@ impl Event {
     }
 }
 
+impl Default for Hypergraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[pymethods]
 impl Hypergraph {
     #[new]
@@ -121,7 +127,7 @@ impl Hypergraph {
     pub fn add_node(&mut self, desc: String) {
         self.nodes.push(Node {
             id: self.nodes.len(),
-            desc: desc,
+            desc,
         });
     }
 
@@ -167,7 +173,7 @@ impl Hypergraph {
 
     #[staticmethod]
     pub fn get_hyper_simulation(query: PyRef<Hypergraph>, data: PyRef<Hypergraph>, delta: PyRef<DeltaPy>, d_match: PyRef<DMatchImpl>) -> HashMap<usize, HashSet<usize>> {
-        let delta_inner = DeltaImpl::from(delta.clone(), &*query, &*data);
+        let delta_inner = DeltaImpl::from(delta.clone(), &query, &data);
         let sim = HyperSimulation::get_hyper_simulation_naive(&*query, &*data, &delta_inner, &*d_match);
         sim.into_iter()
             .map(|(k, v)| (k.id(), v.into_iter().map(|n| n.id()).collect()))
@@ -176,7 +182,7 @@ impl Hypergraph {
 
     #[staticmethod]
     pub fn get_hyper_simulation_strict(query: PyRef<Hypergraph>, data: PyRef<Hypergraph>, delta: PyRef<DeltaPy>, d_match: PyRef<DMatchImpl>) -> HashMap<usize, HashSet<usize>> {
-        let delta_inner = DeltaImpl::from(delta.clone(), &*query, &*data);
+        let delta_inner = DeltaImpl::from(delta.clone(), &query, &data);
         let sim = HyperSimulation::get_hyper_simulation_strict(&*query, &*data, &delta_inner, &*d_match);
         sim.into_iter()
             .map(|(k, v)| (k.id(), v.into_iter().map(|n| n.id()).collect()))
@@ -185,7 +191,7 @@ impl Hypergraph {
 
     pub fn get_hyper_simulation_trace(&self) -> Vec<Event> {
         let trace = HyperSimulationTrace::get_trace("hyper_simulation.trace").unwrap();
-        let events = trace.into_iter().map(|event| {
+        trace.into_iter().map(|event| {
             match event {
                 HSEvent::Base(sc_id, relation) => Event {
                     phrase: "base".to_string(),
@@ -198,8 +204,7 @@ impl Hypergraph {
                     binary_relation: relation
                 }
             }
-        }).collect();
-        events
+        }).collect()
     }
 }
 
@@ -274,7 +279,7 @@ impl<'a> typed::Typed<'a> for Hypergraph {
             // Instead, you need to define what data you want to compare and pass only that.
             // For example, if your Type trait has an id() method, you could do:
             Python::attach(|py| {
-                type_same_fn.call1(py, (&x.id, &y.id)).map_or(false, |result| {
+                type_same_fn.call1(py, (&x.id, &y.id)).is_ok_and(|result| {
                     result.extract::<bool>(py).unwrap_or(false)
                 })
             })
@@ -296,6 +301,12 @@ pub struct LMatchImpl {
     empty_set: HashSet<usize>,
 }
 
+impl Default for LMatchImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[pymethods]
 impl LMatchImpl {
     #[new]
@@ -345,7 +356,7 @@ impl LMatch for LMatchImpl {
             }
         });
 
-        l_match.entry(u).or_insert_with(HashSet::new)
+        l_match.entry(u).or_default()
     }
 
     fn l_match_with_node(&self, e: &Self::Edge, e_prime: &Self::Edge, u: usize) -> &HashSet<usize> {
@@ -354,28 +365,28 @@ impl LMatch for LMatchImpl {
                 return match_set;
             } 
         }
-        return &self.empty_set
+        &self.empty_set
     }
 
     fn dom(&self, e: &Self::Edge, e_prime: &Self::Edge) -> impl Iterator<Item = &usize> {
         if let Some(let_match) = self.l_match_cache.get(&(e.id, e_prime.id)) {
-            return let_match.keys();
+            let_match.keys()
         } else {
             // If the cache does not contain the entry, return an empty iterator
-            return self.empty_match.keys();
+            self.empty_match.keys()
         }
     }
 }
 
 impl<'a> LPredicate<'a> for Hypergraph {
-    fn l_predicate_node(&'a self, u: &'a Self::Node, v: &'a Self::Node) -> bool {
+    fn l_predicate_node(&'a self, _u: &'a Self::Node, _v: &'a Self::Node) -> bool {
         true
     }
 
     fn l_predicate_edge(&'a self, e: &'a Self::Edge, e_prime: &'a Self::Edge) -> bool {
         if let Some(l_predicate_fn) = self.l_predicate_fn.as_ref() {
             Python::attach(|py| {
-                l_predicate_fn.call1(py, (e.clone(), e_prime.clone())).map_or(false, |result| {
+                l_predicate_fn.call1(py, (e.clone(), e_prime.clone())).is_ok_and(|result| {
                     result.extract::<bool>(py).unwrap_or(false)
                 })
             })
@@ -384,7 +395,7 @@ impl<'a> LPredicate<'a> for Hypergraph {
         }
     }
 
-    fn l_predicate_set(&'a self, x: &HashSet<&'a Self::Node>, y: &HashSet<&'a Self::Node>) -> bool {
+    fn l_predicate_set(&'a self, _x: &HashSet<&'a Self::Node>, _y: &HashSet<&'a Self::Node>) -> bool {
         // Implement your logic here
         true
     }
@@ -429,8 +440,10 @@ impl DMatchImpl {
     }
 }
 
+type SematicClusterPairs<'a> = Vec<(SematicCluster<'a, Hyperedge>, SematicCluster<'a, Hyperedge>)>;
+
 struct DeltaImpl<'a> {
-    sematic_cluster: HashMap<(&'a Node, &'a Node), Vec<(SematicCluster<'a, Hyperedge>, SematicCluster<'a, Hyperedge>)>>,
+    sematic_cluster: HashMap<(&'a Node, &'a Node), SematicClusterPairs<'a>>,
 }
 
 impl<'a> Delta<'a> for DeltaImpl<'a> {
@@ -447,7 +460,7 @@ impl<'a> Delta<'a> for DeltaImpl<'a> {
 
 impl<'a> DeltaImpl<'a> {
     fn from(delta: DeltaPy, query: &'a Hypergraph, data: &'a Hypergraph) -> Self {
-        let mut pair_map: HashMap<(&Node, &Node), Vec<(SematicCluster<'a, Hyperedge>, SematicCluster<'a, Hyperedge>)>> = HashMap::new();
+        let mut pair_map: HashMap<(&Node, &Node), SematicClusterPairs<'a>> = HashMap::new();
 
         for u in &query.nodes {
             for v in &data.nodes {
@@ -476,20 +489,20 @@ impl<'a> DeltaImpl<'a> {
             }
         } 
 
-        let res = DeltaImpl {
+        DeltaImpl {
             sematic_cluster: pair_map,
-        };
-
-        return res;
+        }
     }
 
 
 }
 
+type ClusterIdPair = ((Vec<usize>, usize), (Vec<usize>, usize));
+
 #[derive(Clone)]
 #[pyclass(name = "Delta")]
 pub struct DeltaPy {
-    sematic_cluster_cache: HashMap<(usize, usize), Vec<((Vec<usize>, usize), (Vec<usize>, usize))>>, // (u_id, v_id) -> Vec<((q_edge_ids, sc_id), (d_edge_ids, sc_id))>
+    sematic_cluster_cache: HashMap<(usize, usize), Vec<ClusterIdPair>>, // (u_id, v_id) -> Vec<((q_edge_ids, sc_id), (d_edge_ids, sc_id))>
     global_cnt: usize
 }
 
@@ -513,7 +526,7 @@ impl DeltaPy {
             e_ref.id
         }).collect();
         self.sematic_cluster_cache.entry((u.id, v.id)).or_default().push(((u_ids, id), (v_ids, id)));
-        return id;
+        id
     }
 }
 
@@ -0,0 +1,110 @@
+use std::hash::{Hash, Hasher};
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyFloat, PyInt, PyString, PyTuple};
+
+/// A NetworkX node key in a form that can be hashed and compared in Rust.
+///
+/// Keys are normalised the way a Python `dict` sees them, so `True` and `1`
+/// or `1.0` and `1` are the same key while `1` and `"1"` are not. Objects of
+/// any other type keep their own `__hash__` and `__eq__`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NodeKey {
+    None,
+    Int(i64),
+    Float(u64),
+    Str(String),
+    Tuple(Vec<NodeKey>),
+    Object(PyKey),
+}
+
+impl NodeKey {
+    pub fn extract(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if obj.is_none() {
+            return Ok(NodeKey::None);
+        }
+        if obj.is_exact_instance_of::<PyBool>() {
+            return Ok(NodeKey::Int(obj.extract::<bool>()? as i64));
+        }
+        if obj.is_exact_instance_of::<PyInt>() {
+            if let Ok(value) = obj.extract::<i64>() {
+                return Ok(NodeKey::Int(value));
+            }
+        }
+        if obj.is_exact_instance_of::<PyFloat>() {
+            let value = obj.extract::<f64>()?;
+            if value.is_nan() {
+                return Err(ambiguous(obj));
+            }
+            if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 {
+                return Ok(NodeKey::Int(value as i64));
+            }
+            return Ok(NodeKey::Float(value.to_bits()));
+        }
+        if obj.is_exact_instance_of::<PyString>() {
+            return Ok(NodeKey::Str(obj.extract::<String>()?));
+        }
+        if obj.is_exact_instance_of::<PyTuple>() {
+            return obj.try_iter()?
+                .map(|item| NodeKey::extract(&item?))
+                .collect::<PyResult<Vec<_>>>()
+                .map(NodeKey::Tuple);
+        }
+        PyKey::new(obj).map(NodeKey::Object)
+    }
+}
+
+/// Fallback for keys without a Rust counterpart, hashed and compared by Python.
+#[derive(Debug)]
+pub struct PyKey {
+    obj: Py<PyAny>,
+    hash: isize,
+}
+
+impl PyKey {
+    fn new(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let hash = obj.hash().map_err(|err| {
+            let py = obj.py();
+            let error = PyTypeError::new_err(format!("unhashable node key: {}", describe(obj)));
+            error.set_cause(py, Some(err));
+            error
+        })?;
+        // 不等于自身的 key（例如 NaN）在字典里无法再被找到
+        if !obj.eq(obj)? {
+            return Err(ambiguous(obj));
+        }
+        Ok(PyKey { obj: obj.clone().unbind(), hash })
+    }
+}
+
+impl Clone for PyKey {
+    fn clone(&self) -> Self {
+        Python::attach(|py| PyKey { obj: self.obj.clone_ref(py), hash: self.hash })
+    }
+}
+
+impl PartialEq for PyKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+            && Python::attach(|py| self.obj.bind(py).eq(other.obj.bind(py)).unwrap_or(false))
+    }
+}
+
+impl Eq for PyKey {}
+
+impl Hash for PyKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+pub fn describe(obj: &Bound<'_, PyAny>) -> String {
+    obj.repr()
+        .and_then(|repr| repr.extract::<String>())
+        .unwrap_or_else(|_| "<unprintable>".to_string())
+}
+
+fn ambiguous(obj: &Bound<'_, PyAny>) -> PyErr {
+    PyValueError::new_err(format!("ambiguous node key {}: it is not equal to itself", describe(obj)))
+}
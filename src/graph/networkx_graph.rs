@@ -1,13 +1,33 @@
 use graph_base::interfaces::vertex::Vertex;
 use graph_simulation::algorithm::simulation::Simulation;
-use graph_simulation::algorithm::bounded::{BoundedSimulation, Bounded};
-use pyo3::types::PySet;
-use pyo3::{prelude::*, types::PyDict};
+use graph_simulation::algorithm::bounded::{Bounded, BoundedSimulation};
+use crate::algorithm::bisimulation::bisimulation_classes as refine_classes;
+use crate::algorithm::bounded::{witnesses, UNBOUNDED};
+use crate::algorithm::dual::dual_refine;
+use crate::algorithm::explain::{explained_simulation, Removal, Removals};
+use crate::algorithm::fractional::fractional_simulation;
+use crate::algorithm::incremental::{ancestors, maximum_simulation, refine_simulation};
+use crate::algorithm::minimize::minimize;
+use crate::algorithm::strong::{strong_simulation, StrongMatch};
+use crate::algorithm::topk::{degree_score, neighborhood_score, top_k};
+use pyo3::exceptions::{PyKeyError, PyTypeError, PyValueError};
+use pyo3::types::{PyList, PySet, PyTuple};
+use pyo3::{prelude::*, types::PyDict, IntoPyObjectExt};
 use graph_base::interfaces::labeled::{Label, Labeled, LabeledAdjacency};
-use graph_base::interfaces::graph::{Graph, Directed, Adjacency, AdjacencyInv, SingleId, IdPair, Degree, DegreeList};
-
-use pyo3::prelude::*;
-use std::collections::{HashMap, HashSet};
+use graph_base::interfaces::graph::{Graph, Directed, Adjacency, AdjacencyInv, SingleId, IdPair, Degree, DegreeList, AdjacencyList};
+
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+use super::adjacency::AdjacencyIndex;
+use super::error::{CompareFunctionError, UnknownNodeError};
+use super::key::{describe, NodeKey};
+use super::value::AttrValue;
 use std::hash::{Hash, Hasher};
 // use std::path::Display;
 // use std::sync::Arc;
@@ -17,24 +37,31 @@ use std::hash::{Hash, Hasher};
 
 // 自定义图结构
 
-#[derive(Debug)]
-struct Attributes(HashMap<String, Py<PyAny>>);
+// 属性在 from_networkx 时一次性转换为原生值，比较和哈希都不需要 GIL
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Attributes(HashMap<String, AttrValue>);
 
-impl Clone for Attributes {
-    fn clone(&self) -> Self {
-        Python::attach(|py| {
-            let cloned_map = self.0.iter()
-                .map(|(k, v)| (k.clone(), v.clone_ref(py)))
-                .collect();
-            Attributes(cloned_map)
-        })
+impl Attributes {
+    fn extract(attributes: &HashMap<String, Bound<'_, PyAny>>) -> PyResult<Self> {
+        attributes.iter()
+            .map(|(k, v)| Ok((k.clone(), AttrValue::extract(v)?)))
+            .collect::<PyResult<HashMap<_, _>>>()
+            .map(Attributes)
+    }
+
+    fn to_py_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (k, v) in &self.0 {
+            dict.set_item(k, v)?;
+        }
+        Ok(dict)
     }
 }
 
 impl std::fmt::Display for Attributes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut entries: Vec<_> = self.0.iter().collect();
-        entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+        entries.sort_by_key(|(k, _)| *k);
 
         write!(f, "{{")?;
         for (key, value) in entries {
@@ -44,54 +71,12 @@ impl std::fmt::Display for Attributes {
     }
 }
 
-impl PartialEq for Attributes {
-    fn eq(&self, other: &Self) -> bool {
-        // 首先比较长度
-        if self.0.len() != other.0.len() {
-            return false;
-        }
-
-        Python::attach(|py| {
-            self.0.iter().all(|(key, value)| {
-                match other.0.get(key) {
-                    Some(other_value) => {
-                        match value.call_method1(py, "__eq__", (other_value,)) {
-                            Ok(result) => result.extract::<bool>(py).unwrap_or(false),
-                            Err(_) => false,
-                        }
-                    },
-                    None => false,
-                }
-            })
-        })
-    }
-}
-
-impl Eq for Attributes {}
-
 impl Hash for Attributes {
     fn hash<H: Hasher>(&self, state: &mut H) {
         // 确保相同的字典产生相同的哈希值
         let mut entries: Vec<_> = self.0.iter().collect();
-        entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
-
-        Python::attach(|py| {
-            for (key, value) in entries {
-                key.hash(state);
-                // 使用 Python 对象的 __hash__ 方法
-                match value.call_method0(py, "__hash__") {
-                    Ok(hash_result) => {
-                        if let Ok(hash_value) = hash_result.extract::<isize>(py) {
-                            hash_value.hash(state);
-                        }
-                    },
-                    Err(_) => {
-                        // 如果对象不可哈希，使用默认值
-                        0isize.hash(state);
-                    }
-                }
-            }
-        });
+        entries.sort_by_key(|(k, _)| *k);
+        entries.hash(state);
     }
 }
 
@@ -101,10 +86,44 @@ impl Label for Attributes {
     }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+// 节点原生标签的来源
+enum LabelSource {
+    // 按属性名取值组成的列表
+    Keys(Vec<String>),
+    // 用户给出的 attrs -> hashable 函数
+    KeyFn(Py<PyAny>),
+}
+
+impl LabelSource {
+    fn clone_ref(&self, py: Python<'_>) -> Self {
+        match self {
+            LabelSource::Keys(keys) => LabelSource::Keys(keys.clone()),
+            LabelSource::KeyFn(key_fn) => LabelSource::KeyFn(key_fn.clone_ref(py)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Node {
     id: usize,
     attributes: Attributes,
+    // 由 label_keys 或 label_key_fn 得到的原生标签，设置后 label_same 直接在 Rust 中比较
+    label: Option<AttrValue>,
+}
+
+// 节点只在所属图内比较，按下标判等即可
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Node {}
+
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
 impl std::fmt::Display for Node {
@@ -123,7 +142,11 @@ impl SingleId for Node {
 pub struct Edge {
     source: usize,
     target: usize,
+    // 多重图中平行边的 key，普通图为 None
+    key: Option<AttrValue>,
     attributes: Attributes,
+    // 无向图中每条边存两份，`mirror` 标记反向的那一份
+    mirror: bool,
 }
 
 impl IdPair for Edge {
@@ -132,186 +155,373 @@ impl IdPair for Edge {
     }
 }
 
-#[pyclass]
+/// The NetworkX class a graph was loaded from.
+///
+/// Undirected graphs are simulated as symmetric directed graphs: every edge
+/// `{u, v}` is stored as the two arcs `u -> v` and `v -> u`, so a query node
+/// has to find a matching neighbour in either direction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GraphKind {
+    Graph,
+    #[default]
+    DiGraph,
+    MultiGraph,
+    MultiDiGraph,
+}
+
+impl GraphKind {
+    fn new(directed: bool, multigraph: bool) -> Self {
+        match (directed, multigraph) {
+            (false, false) => GraphKind::Graph,
+            (true, false) => GraphKind::DiGraph,
+            (false, true) => GraphKind::MultiGraph,
+            (true, true) => GraphKind::MultiDiGraph,
+        }
+    }
+
+    fn of(nx_graph: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let directed = nx_graph.call_method0("is_directed")?.extract::<bool>()?;
+        let multigraph = nx_graph.call_method0("is_multigraph")?.extract::<bool>()?;
+        Ok(GraphKind::new(directed, multigraph))
+    }
+
+    pub fn is_directed(self) -> bool {
+        matches!(self, GraphKind::DiGraph | GraphKind::MultiDiGraph)
+    }
+
+    pub fn is_multigraph(self) -> bool {
+        matches!(self, GraphKind::MultiGraph | GraphKind::MultiDiGraph)
+    }
+
+    fn class_name(self) -> &'static str {
+        match self {
+            GraphKind::Graph => "Graph",
+            GraphKind::DiGraph => "DiGraph",
+            GraphKind::MultiGraph => "MultiGraph",
+            GraphKind::MultiDiGraph => "MultiDiGraph",
+        }
+    }
+}
+
+/// A graph that can be passed to every simulation function.
+///
+/// Compare functions, label keys and the label cache stay on the graph, so a
+/// data graph that is queried many times is converted and compared only once.
+#[pyclass(name = "Graph")]
 pub struct NetworkXGraph {
+    kind: GraphKind,
     nodes: Vec<Node>,
     edges: Vec<Edge>,
-    node_indices: HashMap<String, usize>,
+    node_indices: HashMap<NodeKey, usize>,
+    node_keys: Vec<Py<PyAny>>,  // 节点下标到原始 networkx 节点的映射
     same_label_fn: Option<Py<PyAny>>,
     same_edge_fn: Option<Py<PyAny>>,
     same_node_edge_fn: Option<Py<PyAny>>,
-    same_label_cache: Option<HashSet<(usize, usize)>>,
+    same_label_cache: Option<LabelCache>,
+    label_source: Option<LabelSource>,
     bound_values: HashMap<usize, usize>,  // 节点 ID 到 bound 值的映射
+    edge_bounds: HashMap<(usize, usize), usize>,  // 边 (u, v) 的 bound 值，优先于 bound_values
+    adjacency: OnceLock<AdjacencyIndex>,  // 第一次使用时构建，加点加边时清空
+    simple_edges: HashMap<(usize, usize), usize>,  // 非多重图中端点对到边下标的映射
+    revision: u64,  // 节点或标签变化时更新，用来判断别的图上的缓存是否过期
+    compared_with: AtomicU64,  // 算法正在与之比较的图的 revision，label_same 用它检查缓存
+}
+
+// 用某个比较函数得到的、与另一张图（某个 revision）之间标签相容的节点对
+struct LabelCache {
+    other: u64,
+    compare: Option<Py<PyAny>>,
+    pairs: HashSet<(usize, usize)>,
+}
+
+impl Clone for LabelCache {
+    fn clone(&self) -> Self {
+        Python::attach(|py| LabelCache {
+            other: self.other,
+            compare: self.compare.as_ref().map(|f| f.clone_ref(py)),
+            pairs: self.pairs.clone(),
+        })
+    }
+}
+
+// 是否是同一个 Python 对象，不需要 GIL
+fn same_object(a: &Option<Py<PyAny>>, b: &Option<Py<PyAny>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.as_ptr() == b.as_ptr(),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn next_revision() -> u64 {
+    static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
 }
 
 impl Clone for NetworkXGraph {
     fn clone(&self) -> Self {
         Python::attach(|py| {
             NetworkXGraph {
+                kind: self.kind,
                 nodes: self.nodes.clone(),
                 edges: self.edges.clone(),
                 node_indices: self.node_indices.clone(),
+                node_keys: self.node_keys.iter().map(|k| k.clone_ref(py)).collect(),
                 same_label_fn: self.same_label_fn.as_ref().map(|f| f.clone_ref(py)),
                 same_edge_fn: self.same_edge_fn.as_ref().map(|f| f.clone_ref(py)),
                 same_node_edge_fn: self.same_node_edge_fn.as_ref().map(|f| f.clone_ref(py)),
                 same_label_cache: self.same_label_cache.clone(),
+                label_source: self.label_source.as_ref().map(|source| source.clone_ref(py)),
                 bound_values: self.bound_values.clone(),
+                edge_bounds: self.edge_bounds.clone(),
+                adjacency: self.adjacency.clone(),
+                simple_edges: self.simple_edges.clone(),
+                revision: self.revision,
+                compared_with: AtomicU64::new(self.compared_with.load(Ordering::Relaxed)),
             }
         })
     }
 }
 
-fn convert_to_string(obj: &Py<PyAny>) -> PyResult<String> {
-    Python::attach(|py| {
-        // Try direct conversion first
-        obj.call_method0(py, "__str__")?.extract::<String>(py)
-            .or_else(|_| {
-                // If that fails, try to convert to a string using repr
-                obj.call_method0(py, "__repr__")?.extract::<String>(py)
-            })
-    })
-}
-
 #[pymethods]
 impl NetworkXGraph {
     #[new]
-    fn new() -> Self {
-        NetworkXGraph {
-            nodes: Vec::new(),
-            edges: Vec::new(),
-            node_indices: HashMap::new(),
-            same_label_fn: None,
-            same_edge_fn: None,
-            same_node_edge_fn: None, 
-            same_label_cache: None,
-            bound_values: HashMap::new(),
-        }
+    #[pyo3(signature = (directed = true, multigraph = false))]
+    fn py_new(directed: bool, multigraph: bool) -> Self {
+        let mut graph = NetworkXGraph::new();
+        graph.kind = GraphKind::new(directed, multigraph);
+        graph
     }
 
     // 从NetworkX图转换的静态方法
     #[staticmethod]
     fn from_networkx(nx_graph: &Bound<'_, PyAny>) -> PyResult<Self> {
-        let nodes = nx_graph.getattr("nodes")?.call_method1("items", ())?;
-        let edges = nx_graph.getattr("edges")?.call_method1("data", ())?;
-
         let mut graph = NetworkXGraph::new();
+        graph.kind = GraphKind::of(nx_graph)?;
+
+        let nodes = nx_graph.getattr("nodes")?.call_method1("items", ())?;
+        // 多重图需要带上 key，否则平行边无法区分
+        let edges = if graph.kind.is_multigraph() {
+            let kwargs = PyDict::new(nx_graph.py());
+            kwargs.set_item("keys", true)?;
+            kwargs.set_item("data", true)?;
+            nx_graph.getattr("edges")?.call((), Some(&kwargs))?
+        } else {
+            nx_graph.getattr("edges")?.call_method1("data", ())?
+        };
 
         for node in nodes.try_iter()? {
             let node = node?;
-            let id = node.call_method1("__getitem__", (0, ))?.extract::<PyObject>()?;
-            let id = convert_to_string(&id)?;
-            let attrs = node.call_method1("__getitem__", (1, ))?.extract::<HashMap<String, PyObject>>()?;
-            graph.add_node(id, attrs);
+            let id = node.call_method1("__getitem__", (0, ))?;
+            let attrs = node.call_method1("__getitem__", (1, ))?.extract::<HashMap<String, Bound<'_, PyAny>>>()?;
+            graph.add_node(&id, Some(attrs))?;
         }
         for edge in edges.try_iter()? {
             let edge = edge?;
-            let source = edge.call_method1("__getitem__", (0, ))?.extract::<PyObject>()?;
-            let source = convert_to_string(&source)?;
-            let target = edge.call_method1("__getitem__", (1, ))?.extract::<PyObject>()?;
-            let target = convert_to_string(&target)?;
-            let attrs = edge.call_method1("__getitem__", (2, ))?.extract::<HashMap<String, PyObject>>()?;
-            graph.add_edge(source, target, attrs);
+            let source = edge.call_method1("__getitem__", (0, ))?;
+            let target = edge.call_method1("__getitem__", (1, ))?;
+            let (key, attrs) = if graph.kind.is_multigraph() {
+                let key = edge.call_method1("__getitem__", (2, ))?;
+                (Some(key), edge.call_method1("__getitem__", (3, ))?)
+            } else {
+                (None, edge.call_method1("__getitem__", (2, ))?)
+            };
+            let attrs = attrs.extract::<HashMap<String, Bound<'_, PyAny>>>()?;
+            graph.add_edge(&source, &target, key.as_ref(), Some(attrs))?;
         }
         
         Ok(graph)
     }
 
     // 转回NetworkX图的方法
-    fn to_networkx(&self, py: Python) -> PyResult<PyObject> {
+    fn to_networkx(&self, py: Python) -> PyResult<Py<PyAny>> {
         let nx = py.import("networkx")?;
-        let graph = nx.getattr("Graph")?.call0()?;
+        let graph = nx.getattr(self.kind.class_name())?.call0()?;
 
         // 添加节点
         for node in &self.nodes {
-            let attrs_dict = PyDict::new(py);
-            for (k, v) in &node.attributes.0 {
-                attrs_dict.set_item(k, v.clone_ref(py))?;
-            }
-            graph.call_method1(
-                "add_node",
-                (node.id.clone(), attrs_dict),
-            )?;
+            let attrs_dict = node.attributes.to_py_dict(py)?;
+            graph.call_method("add_node", (self.node_key(py, node),), Some(&attrs_dict))?;
         }
 
         // 添加边
-        for edge in &self.edges {
-            let attrs_dict = PyDict::new(py);
-            for (k, v) in &edge.attributes.0 {
-                attrs_dict.set_item(k, v.clone_ref(py))?;
+        for edge in self.edges.iter().filter(|e| !e.mirror) {
+            let attrs_dict = edge.attributes.to_py_dict(py)?;
+            if let Some(key) = &edge.key {
+                attrs_dict.set_item("key", key)?;
             }
-            graph.call_method1(
-                "add_edge",
-                (
-                    edge.source.clone(),
-                    edge.target.clone(),
-                    attrs_dict,
-                ),
-            )?;
+            let (source, target) = (&self.node_keys[edge.source], &self.node_keys[edge.target]);
+            graph.call_method("add_edge", (source.clone_ref(py), target.clone_ref(py)), Some(&attrs_dict))?;
         }
 
         Ok(graph.into())
     }
 
-    // 其他有用的方法
-    fn add_node(&mut self, id: String, attributes: HashMap<String, PyObject>) {
+    // 与 networkx 一样，重复添加节点时更新它的属性
+    #[pyo3(signature = (id, **attributes))]
+    fn add_node(&mut self, id: &Bound<'_, PyAny>, attributes: Option<HashMap<String, Bound<'_, PyAny>>>) -> PyResult<()> {
+        let key = NodeKey::extract(id)?;
+        let attributes = Attributes::extract(&attributes.unwrap_or_default())?;
+        self.revision = next_revision();
+        self.same_label_cache = None;
+        if let Some(&index) = self.node_indices.get(&key) {
+            let mut merged = self.nodes[index].attributes.clone();
+            merged.0.extend(attributes.0);
+            self.nodes[index].label = self.project_label(id.py(), &merged)?;
+            self.nodes[index].attributes = merged;
+            return Ok(());
+        }
         let index = self.nodes.len();
-        self.node_indices.insert(id.clone(), index);
-        let attributes = Attributes(attributes);
-        self.nodes.push(Node { id: index, attributes });
+        self.node_indices.insert(key, index);
+        self.node_keys.push(id.clone().unbind());
+        let label = self.project_label(id.py(), &attributes)?;
+        self.nodes.push(Node { id: index, attributes, label });
+        self.adjacency.take();
+        Ok(())
     }
 
+    // 两端节点必须已经存在；非多重图中重复加边只更新属性
+    #[pyo3(signature = (source, target, key = None, **attributes))]
     fn add_edge(
         &mut self,
-        source: String,
-        target: String,
-        attributes: HashMap<String, PyObject>,
-    ) {
-        let attributes = Attributes(attributes);
-        let source = *self.node_indices.get(&source).unwrap();
-        let target = *self.node_indices.get(&target).unwrap();
+        source: &Bound<'_, PyAny>,
+        target: &Bound<'_, PyAny>,
+        key: Option<&Bound<'_, PyAny>>,
+        attributes: Option<HashMap<String, Bound<'_, PyAny>>>,
+    ) -> PyResult<()> {
+        let attributes = Attributes::extract(&attributes.unwrap_or_default())?;
+        let key = key.map(AttrValue::extract).transpose()?;
+        let source = self.node_index(source)?;
+        let target = self.node_index(target)?;
+        if !self.kind.is_multigraph() {
+            let pair = if self.kind.is_directed() { (source, target) } else { (source.min(target), source.max(target)) };
+            if let Some(&index) = self.simple_edges.get(&pair) {
+                // 镜像边总是紧挨在原边之前
+                let first = if index > 0 && self.edges[index - 1].mirror { index - 1 } else { index };
+                for edge in &mut self.edges[first..=index] {
+                    edge.attributes.0.extend(attributes.0.clone());
+                }
+                return Ok(());
+            }
+            self.simple_edges.insert(pair, self.edges.len() + usize::from(!self.kind.is_directed() && source != target));
+        }
+        if !self.kind.is_directed() && source != target {
+            self.edges.push(Edge {
+                source: target,
+                target: source,
+                key: key.clone(),
+                attributes: attributes.clone(),
+                mirror: true,
+            });
+        }
         self.edges.push(Edge {
             source,
             target,
+            key,
             attributes,
+            mirror: false,
         });
+        self.adjacency.take();
+        Ok(())
+    }
+
+    // 与 networkx 一样，多重图不给 key 时删除最后加入的那条平行边
+    #[pyo3(signature = (source, target, key = None))]
+    fn remove_edge(&mut self, source: &Bound<'_, PyAny>, target: &Bound<'_, PyAny>, key: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        let key = key.map(AttrValue::extract).transpose()?;
+        let (s, t) = (self.node_index(source)?, self.node_index(target)?);
+        let directed = self.kind.is_directed();
+        let index = self.edges.iter()
+            .rposition(|edge| {
+                let ends = (edge.source, edge.target);
+                !edge.mirror
+                    && (ends == (s, t) || (!directed && ends == (t, s)))
+                    && key.as_ref().is_none_or(|key| edge.key.as_ref() == Some(key))
+            })
+            .ok_or_else(|| PyKeyError::new_err(format!("no edge ({}, {})", describe(source), describe(target))))?;
+        self.remove_edge_at(index);
+        Ok(())
+    }
+
+    // 删除节点和与它相连的边，之后的节点下标依次前移
+    fn remove_node(&mut self, id: &Bound<'_, PyAny>) -> PyResult<()> {
+        let index = self.node_index(id)?;
+        self.remove_node_at(index);
+        Ok(())
     }
 
     fn node_count(&self) -> usize {
         self.nodes.len()
     }
 
+    fn __len__(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Graph({} with {} nodes and {} edges)", self.kind.class_name(), self.node_count(), self.edge_count())
+    }
+
     fn edge_count(&self) -> usize {
-        self.edges.len()
+        self.edges.iter().filter(|e| !e.mirror).count()
+    }
+
+    fn is_directed(&self) -> bool {
+        self.kind.is_directed()
+    }
+
+    fn is_multigraph(&self) -> bool {
+        self.kind.is_multigraph()
     }
 
     // 获取节点属性
-    fn get_node_attributes(&self, node_id: &str) -> Option<HashMap<String, PyObject>> {
-        self.node_indices.get(node_id).map(|&index| {
-            Python::with_gil(|py| {
-                self.nodes[index].attributes.0.iter()
-                    .map(|(k, v)| (k.clone(), v.clone_ref(py)))
-                    .collect()
-            })
-        })
+    fn get_node_attributes<'py>(&self, py: Python<'py>, node_id: &Bound<'py, PyAny>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.node_indices.get(&NodeKey::extract(node_id)?).map(|&index| {
+            self.nodes[index].attributes.to_py_dict(py)
+        }).transpose()
     }
 
-    // 获取边属性
+    // 获取边属性；多重图不给 key 时与 networkx 的 get_edge_data 一样返回 {key: attrs}
+    #[pyo3(signature = (source, target, key = None))]
     fn get_edge_attributes(
         &self,
-        source: &str,
-        target: &str,
-    ) -> Option<HashMap<String, PyObject>> {
-        self.edges
-            .iter()
-            .find(|e| e.source == *self.node_indices.get(source).unwrap() 
-                            && e.target == *self.node_indices.get(target).unwrap())
-            .map(|e| Python::with_gil(|py| {
-                e.attributes.0.iter()
-                    .map(|(k, v)| (k.clone(), v.clone_ref(py)))
-                    .collect()
-            }))
+        py: Python<'_>,
+        source: &Bound<'_, PyAny>,
+        target: &Bound<'_, PyAny>,
+        key: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Option<Py<PyAny>>> {
+        let (source, target) = (NodeKey::extract(source)?, NodeKey::extract(target)?);
+        let (Some(&source), Some(&target)) = (self.node_indices.get(&source), self.node_indices.get(&target)) else {
+            return Ok(None);
+        };
+        let mut parallel = self.edges.iter().filter(|e| e.source == source && e.target == target);
+
+        if !self.kind.is_multigraph() {
+            return parallel.next().map(|e| e.attributes.to_py_dict(py)?.into_py_any(py)).transpose();
+        }
+
+        match key.map(AttrValue::extract).transpose()? {
+            Some(key) => parallel
+                .find(|e| e.key.as_ref() == Some(&key))
+                .map(|e| e.attributes.to_py_dict(py)?.into_py_any(py))
+                .transpose(),
+            None => {
+                let by_key = PyDict::new(py);
+                for edge in parallel {
+                    if let Some(key) = &edge.key {
+                        by_key.set_item(key, edge.attributes.to_py_dict(py)?)?;
+                    }
+                }
+                if by_key.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(by_key.into_any().unbind()))
+                }
+            }
+        }
     }
 
+    // 缓存记着构建它的比较函数，换了函数不会误用，每次调用传入同一个函数时缓存可以继续使用
     fn register_compare_fn(&mut self, compare: Py<PyAny>) {
         self.same_label_fn = Some(compare);
     }
@@ -324,35 +534,98 @@ impl NetworkXGraph {
         self.same_node_edge_fn = Some(compare);
     }
 
-    fn build_compare_cache(&mut self, other: &NetworkXGraph) {
+    // 预先计算两图之间所有标签相容的节点对，返回相容的节点对数量
+    fn build_compare_cache(&mut self, py: Python<'_>, other: &NetworkXGraph) -> PyResult<usize> {
+        self.same_label_cache = None;
 
-        let cache: HashSet<_> = self.nodes.iter().flat_map(|node1| {
-            other.nodes.iter().filter_map(|node2| {
-                if self.label_same(node1, node2) {
-                    Some((node1.id, node2.id))
-                } else {
-                    None
-                }
-            }).collect::<HashSet<_>>()
-        }).collect();
+        let cache: HashSet<_> = if let Some(buckets) = self.label_buckets(other) {
+            // 只在标签相同的组内产生候选对，再交给 same_label_fn 细化
+            let candidates = self.nodes.iter().flat_map(|node1| {
+                let bucket = node1.label.as_ref().and_then(|label| buckets.get(label));
+                bucket.into_iter().flatten().map(move |node2| (node1, *node2))
+            });
+            if self.same_label_fn.is_none() {
+                candidates.map(|(node1, node2)| (node1.id, node2.id)).collect()
+            } else {
+                self.compare_in_batches(py, other, candidates)?
+            }
+        } else if self.same_label_fn.is_none() {
+            // 纯 Rust 比较，释放 GIL 并行计算
+            let (graph1, graph2) = (&*self, other);
+            py.detach(|| {
+                graph1.nodes.par_iter().flat_map_iter(|node1| {
+                    graph2.nodes.iter()
+                        .filter(move |node2| graph1.native_label_same(node1, node2) == Some(true))
+                        .map(move |node2| (node1.id, node2.id))
+                }).collect()
+            })
+        } else {
+            let candidates = self.nodes.iter()
+                .flat_map(|node1| other.nodes.iter().map(move |node2| (node1, node2)));
+            self.compare_in_batches(py, other, candidates)?
+        };
+
+        let count = cache.len();
+        let compare = self.same_label_fn.as_ref().map(|f| f.clone_ref(py));
+        self.same_label_cache = Some(LabelCache { other: other.revision, compare, pairs: cache });
+        Ok(count)
+    }
+
+    // 按给定的属性名取出每个节点的原生标签，之后的标签比较不再回调 Python
+    fn set_label_keys(&mut self, py: Python<'_>, keys: Vec<String>) -> PyResult<()> {
+        if matches!(&self.label_source, Some(LabelSource::Keys(current)) if *current == keys) {
+            return Ok(());
+        }
+        self.label_source = Some(LabelSource::Keys(keys));
+        self.relabel(py)
+    }
 
-        self.same_label_cache = Some(cache);
+    // 按 key_fn(attrs) 的返回值给节点分组，只有同组的节点才可能标签相容
+    fn register_label_key_fn(&mut self, py: Python<'_>, key_fn: Py<PyAny>) -> PyResult<()> {
+        if matches!(&self.label_source, Some(LabelSource::KeyFn(current)) if current.bind(py).is(key_fn.bind(py))) {
+            return Ok(());
+        }
+        self.label_source = Some(LabelSource::KeyFn(key_fn));
+        self.relabel(py)
     }
 
-    fn set_bound_values(&mut self, bound_fn: Py<PyAny>) {
-        // 使用 bound 函数为每个节点设置 bound 值
+    // bound_fn(attrs) 给出从该节点出发的所有边的 bound 值，调用失败或返回值无效时为 0
+    fn set_bound_values(&mut self, py: Python<'_>, bound_fn: Py<PyAny>) -> PyResult<()> {
+        self.edge_bounds.clear();
         for node in &self.nodes {
-            let bound_value = Python::attach(|py| {
-                let node_attrs = node.attributes.0.iter()
-                    .map(|(k, v)| (k.clone(), v.clone_ref(py)))
-                    .collect::<HashMap<_, _>>();
-                bound_fn.call1(py, (node_attrs,))
-                    .ok()
-                    .and_then(|result| result.extract::<usize>(py).ok())
-                    .unwrap_or(0)
-            });
-            self.bound_values.insert(node.id, bound_value);
+            let bound = bound_fn.bind(py).call1((node.attributes.to_py_dict(py)?,))
+                .map_or(0, |value| parse_bound(&value));
+            self.bound_values.insert(node.id, bound);
+        }
+        Ok(())
+    }
+
+    // bound 可以是边属性名、对每条边调用的 bound(src_attrs, edge_attrs, dst_attrs)、
+    // 所有边共用的 int，或者表示不限长度的 "*"
+    fn set_edge_bounds(&mut self, bound: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = bound.py();
+        let mut edge_bounds: HashMap<(usize, usize), usize> = HashMap::new();
+        for edge in &self.edges {
+            let value = if bound.is_callable() {
+                let (source, target) = (&self.nodes[edge.source], &self.nodes[edge.target]);
+                bound.call1((source.attributes.to_py_dict(py)?, edge.attributes.to_py_dict(py)?, target.attributes.to_py_dict(py)?))?
+            } else if bound.extract::<String>().is_ok_and(|name| name != "*") {
+                // 没有该属性的边沿用源节点的 bound
+                let name = bound.extract::<String>()?;
+                match edge.attributes.0.get(&name) {
+                    Some(value) => value.into_pyobject(py)?,
+                    None => continue,
+                }
+            } else {
+                bound.clone()
+            };
+            // 平行边中最严格的 bound 同时满足其余的边
+            let parsed = parse_bound(&value);
+            let entry = edge_bounds.entry((edge.source, edge.target)).or_insert(parsed);
+            *entry = (*entry).min(parsed);
         }
+        self.edge_bounds = edge_bounds;
+        Ok(())
     }
 }
 
@@ -365,14 +638,22 @@ impl<'a> Graph<'a> for NetworkXGraph {
 
     fn new() -> Self {
         NetworkXGraph {
+            kind: GraphKind::default(),
             nodes: Vec::new(),
             edges: Vec::new(),
             node_indices: HashMap::new(),
+            node_keys: Vec::new(),
             same_label_fn: None,
             same_edge_fn: None,
             same_node_edge_fn: None,
             same_label_cache: None,
+            label_source: None,
             bound_values: HashMap::new(),
+            edge_bounds: HashMap::new(),
+            adjacency: OnceLock::new(),
+            simple_edges: HashMap::new(),
+            revision: next_revision(),
+            compared_with: AtomicU64::new(u64::MAX),
         }
     }
 
@@ -384,60 +665,44 @@ impl<'a> Graph<'a> for NetworkXGraph {
         self.edges.iter()
     }
 
+    // 节点 ID 就是它在 nodes 中的下标，不需要再建 id_map
     fn get_edges_pair(&'a self) -> impl Iterator<Item = (&'a Self::Node, &'a Self::Node)> {
-        let id_map: HashMap<_, _, std::collections::hash_map::RandomState> = HashMap::from_iter(self.nodes.iter().map(|node| (node.id, node)));
-        self.edges.iter().map(|edge| (id_map.get(&edge.source).unwrap().clone(), id_map.get(&edge.target).unwrap().clone()) ).collect::<Vec<_>>().into_iter()
+        self.edges.iter().map(|edge| (&self.nodes[edge.source], &self.nodes[edge.target]))
     }
 
-    fn add_node(&mut self, node: Self::Node) {
+    fn get_edges_pair_with_edge(&'a self) -> impl Iterator<Item = (&'a Self::Node, &'a Self::Edge, &'a Self::Node)> {
+        self.edges.iter().map(|edge| (&self.nodes[edge.source], edge, &self.nodes[edge.target]))
+    }
+
+    fn add_node(&mut self, mut node: Self::Node) {
         let index = self.nodes.len();
-        self.node_indices.insert(format!("Node{}.", index), index);
+        node.id = index;
+        self.node_indices.insert(NodeKey::Int(index as i64), index);
+        self.node_keys.push(Python::attach(|py| index.into_py_any(py).expect("int conversion cannot fail")));
         self.nodes.push(node);
+        self.adjacency.take();
     }
 
     fn add_edge(&mut self, edge: Self::Edge) {
         self.edges.push(edge);
+        self.adjacency.take();
     }
 }
 
-fn test_eq(a: &Py<PyAny>, b: &Py<PyAny>) -> bool {
-    Python::attach(|py| {
-        match a.call_method1(py, "__eq__", (b,)) {
-            Ok(result) => result.extract::<bool>(py).unwrap_or(false),
-            Err(_) => false
-        }
-    })
+// a 中的每个属性都要在 b 中存在且相等
+fn native_same_label_fn(a: &Attributes, b: &Attributes) -> bool {
+    a.0.iter().all(|(k, v)| b.0.get(k) == Some(v))
 }
 
-fn native_same_label_fn(a: &Attributes, b: &Attributes) -> bool {
-    for (k, v) in &a.0 {
-        if let Some(other_v) = b.0.get(k) {
-            if test_eq(v, other_v) {
-                continue;
-            }
-        } else {
-            return false;
-        }
-    }
-    true
+// 两个端点都有原生标签时直接比较标签
+fn endpoint_label_same(node1: &Node, node2: &Node) -> Option<bool> {
+    Some(node1.label.as_ref()? == node2.label.as_ref()?)
 }
 
 impl<'a> Labeled<'a> for NetworkXGraph {
+    // 只拿到两个节点，对方图的 revision 由 checked 记下
     fn label_same(&self, node: &Self::Node, label: &Self::Node) -> bool {
-
-        if let Some(cache) = self.same_label_cache.as_ref() {
-            return cache.contains(&(node.id, label.id));
-        }
-
-        if let Some(compare_fn) = self.same_label_fn.as_ref() {
-            Python::attach(|py| {
-                let attr1 = node.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                let attr2 = label.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                compare_fn.call1(py,(attr1, attr2)).unwrap().extract::<bool>(py).unwrap()
-            })
-        } else {
-            return native_same_label_fn(&node.attributes, &label.attributes);
-        }
+        self.compatible(self.compared_with.load(Ordering::Relaxed), node, label)
     }
 
     fn get_label(&'a self, node: &'a Self::Node) -> &'a impl Label {
@@ -445,16 +710,15 @@ impl<'a> Labeled<'a> for NetworkXGraph {
     }
 
     fn get_edges_pair_label(&'a self) -> impl Iterator<Item = (&'a Self::Node, &'a Self::Node, &'a impl Label)> {
-        let id_map: HashMap<_, _, std::collections::hash_map::RandomState> = HashMap::from_iter(self.nodes.iter().map(|node| (node.id, node)));
-        self.edges.iter().map(move |edge| (id_map.get(&edge.source).unwrap().clone(), id_map.get(&edge.target).unwrap().clone(), &edge.attributes)).collect::<Vec<_>>().into_iter()
+        self.edges.iter().map(|edge| (&self.nodes[edge.source], &self.nodes[edge.target], &edge.attributes))
     }
 
     fn edge_label_same(&self, edge1: &Self::Edge, edge2: &Self::Edge) -> bool {
         if let Some(compare_fn) = self.same_edge_fn.as_ref() {
-            Python::attach(|py| {
-                let attr1 = edge1.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                let attr2 = edge2.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                compare_fn.call1(py,(attr1, attr2)).unwrap().extract::<bool>(py).unwrap()
+            guarded_compare(|| Compared::Edges(edge1.pair(), edge2.pair()), |py| {
+                let attr1 = edge1.attributes.to_py_dict(py)?;
+                let attr2 = edge2.attributes.to_py_dict(py)?;
+                compare_result(compare_fn.bind(py).call1((attr1, attr2))?)
             })
         } else {
             native_same_label_fn(&edge1.attributes, &edge2.attributes)
@@ -462,19 +726,24 @@ impl<'a> Labeled<'a> for NetworkXGraph {
     }
 
     fn edge_node_label_same(&self, src1: &Self::Node, edge1: &Self::Edge, dst1: &Self::Node, src2: &Self::Node, edge2: &Self::Edge, dst2: &Self::Node) -> bool {
+        // 端点的原生标签不同就不必再调用比较函数
+        let (src_same, dst_same) = (endpoint_label_same(src1, src2), endpoint_label_same(dst1, dst2));
+        if src_same == Some(false) || dst_same == Some(false) {
+            return false;
+        }
         if let Some(compare_fn) = self.same_node_edge_fn.as_ref() {
-            Python::attach(|py| {
-                let src1_attr = src1.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                let dst1_attr = dst1.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                let edge1_attr = edge1.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                let src2_attr = src2.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                let dst2_attr = dst2.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                let edge2_attr = edge2.attributes.0.iter().map(|(k, v)| (k.clone(), v.clone_ref(py))).collect::<HashMap<_, _>>();
-                compare_fn.call1(py,(src1_attr, edge1_attr, dst1_attr, src2_attr, edge2_attr, dst2_attr)).unwrap().extract::<bool>(py).unwrap()
+            guarded_compare(|| Compared::Edges((src1.id, dst1.id), (src2.id, dst2.id)), |py| {
+                let src1_attr = src1.attributes.to_py_dict(py)?;
+                let dst1_attr = dst1.attributes.to_py_dict(py)?;
+                let edge1_attr = edge1.attributes.to_py_dict(py)?;
+                let src2_attr = src2.attributes.to_py_dict(py)?;
+                let dst2_attr = dst2.attributes.to_py_dict(py)?;
+                let edge2_attr = edge2.attributes.to_py_dict(py)?;
+                compare_result(compare_fn.bind(py).call1((src1_attr, edge1_attr, dst1_attr, src2_attr, edge2_attr, dst2_attr))?)
             })
         } else {
-            native_same_label_fn(&src1.attributes, &src2.attributes) 
-                && native_same_label_fn(&dst1.attributes, &dst2.attributes) 
+            src_same.unwrap_or_else(|| native_same_label_fn(&src1.attributes, &src2.attributes))
+                && dst_same.unwrap_or_else(|| native_same_label_fn(&dst1.attributes, &dst2.attributes))
                 && native_same_label_fn(&edge1.attributes, &edge2.attributes)
         }
     }
@@ -484,240 +753,1691 @@ impl LabeledAdjacency<'_> for NetworkXGraph {}
 
 impl std::fmt::Display for NetworkXGraph {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "NetworkXGraph(\n")?;
-        write!(f, "Nodes: [\n")?;
+        writeln!(f, "NetworkXGraph(")?;
+        writeln!(f, "Nodes: [")?;
         for node in &self.nodes {
-            write!(f, "  {},\n", node)?;
+            writeln!(f, "  {},", node)?;
         }
-        write!(f, "],\n")?;
-        write!(f, "Edges: [\n")?;
+        writeln!(f, "],")?;
+        writeln!(f, "Edges: [")?;
         for edge in &self.edges {
             write!(f, "{} -> {}, ", edge.source, edge.target)?;
         }
-        write!(f, "]\n")?;
+        writeln!(f, "]")?;
         write!(f, ")")
     }
 }
 
-fn to_nx_node(_py: Python, node: &Node) -> PyResult<Py<PyAny>> {
-    // Return the node id as a Python object
-    Python::attach(|py| {
-        let builtins = py.import("builtins")?;
-        let int_fn = builtins.getattr("int")?;
-        let val = int_fn.call1((node.id,))?;
-        Ok(val.into())
-    })
+// 比较的是哪一对节点或哪一对边（两端节点 ID）
+enum Compared {
+    Nodes(usize, usize),
+    Edges((usize, usize), (usize, usize)),
+    Scored(usize, usize),
 }
 
-#[pyfunction]
-#[pyo3(signature = (nx_graph1, nx_graph2, is_label_cached = false))]
-pub fn get_simulation_inter(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, is_label_cached: bool) -> PyResult<Py<PyAny>> {
-    let mut graph1 = NetworkXGraph::from_networkx(nx_graph1)?;
-    let graph2 = NetworkXGraph::from_networkx(nx_graph2)?;
+// 算法内部的比较只能返回 bool，回调出错时记下第一个错误，之后的比较直接返回 false，
+// 等算法结束后由 checked 抛出
+thread_local! {
+    static COMPARE_FAILURE: RefCell<Option<(Compared, PyErr)>> = const { RefCell::new(None) };
+}
 
-    if is_label_cached {
-        graph1.build_compare_cache(&graph2);
+fn guarded_compare(compared: impl FnOnce() -> Compared, compare: impl FnOnce(Python<'_>) -> PyResult<bool>) -> bool {
+    if COMPARE_FAILURE.with_borrow(Option::is_some) {
+        return false;
     }
-
-    let sim = graph1.get_simulation_inter(&graph2);
-    
-
-    // Convert simulation to a list of pairs (i, j) where i is a node in graph1, j is a node in graph2
-    Python::attach(|py| {
-        let map = PyDict::new(py);
-        
-        for (node, set) in sim.iter() {
-            let py_set = PySet::new(py, set.iter().map(|node| to_nx_node(py, node)).collect::<PyResult<Vec<_>>>()?)?;
-            map.set_item(to_nx_node(py, node)?, py_set)?;
-        }
-    
-        Ok(map.into())
+    Python::attach(compare).unwrap_or_else(|err| {
+        COMPARE_FAILURE.with_borrow_mut(|failure| *failure = Some((compared(), err)));
+        false
     })
 }
 
-#[pyfunction]
-#[pyo3(signature = (nx_graph1, nx_graph2, is_label_cached = false))]
-pub fn is_simulation_isomorphic(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, is_label_cached: bool) -> PyResult<bool> {
-    let mut graph1 = NetworkXGraph::from_networkx(nx_graph1)?;
-    let graph2 = NetworkXGraph::from_networkx(nx_graph2)?;
+fn compare_result(result: Bound<'_, PyAny>) -> PyResult<bool> {
+    result.extract::<bool>().map_err(|_| {
+        let type_name = result.get_type().name().map(|name| name.to_string()).unwrap_or_default();
+        PyTypeError::new_err(format!("compare function must return a bool, not {}", type_name))
+    })
+}
 
-    if is_label_cached {
-        graph1.build_compare_cache(&graph2);
+fn score_result(result: Bound<'_, PyAny>) -> PyResult<f64> {
+    let type_name = result.get_type().name().map(|name| name.to_string()).unwrap_or_default();
+    let score = result.extract::<f64>()
+        .map_err(|_| PyTypeError::new_err(format!("compare function must return a score in [0, 1], not {}", type_name)))?;
+    if !(0.0..=1.0).contains(&score) {
+        return Err(PyValueError::new_err(format!("compare function must return a score in [0, 1], not {}", score)));
     }
+    Ok(score)
+}
 
-    Ok(NetworkXGraph::has_simulation(graph1.get_simulation_inter(&graph2)))
+// 节点属性第一次用到时才转换成 dict
+fn cached_dict<'a, 'py>(py: Python<'py>, dicts: &'a mut [Option<Bound<'py, PyDict>>], node: &Node) -> PyResult<&'a Bound<'py, PyDict>> {
+    if dicts[node.id].is_none() {
+        dicts[node.id] = Some(node.attributes.to_py_dict(py)?);
+    }
+    Ok(dicts[node.id].as_ref().unwrap())
 }
 
-#[pyfunction]
-#[pyo3(signature = (nx_graph1, nx_graph2, compare, is_label_cached = false))]
-pub fn get_simulation_inter_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, compare: Py<PyAny>, is_label_cached: bool) -> PyResult<Py<PyAny>> {
-    let mut graph1 = NetworkXGraph::from_networkx(nx_graph1)?;
-    let graph2 = NetworkXGraph::from_networkx(nx_graph2)?;
+// 每批回调之间检查一次 Ctrl-C 等信号
+const COMPARE_BATCH_SIZE: usize = 4096;
 
-    graph1.register_compare_fn(compare);
-    
-    if is_label_cached {
-        graph1.build_compare_cache(&graph2);
+impl NetworkXGraph {
+    // 与 revision 为 other 的图中的节点比较；缓存不是为这张图构建的就不使用
+    fn compatible(&self, other: u64, node: &Node, label: &Node) -> bool {
+        if let Some(cache) = self.label_cache(other) {
+            return cache.pairs.contains(&(node.id, label.id));
+        }
+
+        if let Some(same) = self.native_label_same(node, label) {
+            return same;
+        }
+
+        self.callback_label_same(node, label)
     }
 
-    let sim = graph1.get_simulation_inter(&graph2);
+    fn callback_label_same(&self, node: &Node, label: &Node) -> bool {
+        let compare_fn = self.same_label_fn.as_ref().unwrap();
+        guarded_compare(|| Compared::Nodes(node.id, label.id), |py| {
+            let attr1 = node.attributes.to_py_dict(py)?;
+            let attr2 = label.attributes.to_py_dict(py)?;
+            compare_result(compare_fn.bind(py).call1((attr1, attr2))?)
+        })
+    }
 
-    Python::attach(|py| {
-        let map = PyDict::new(py);
-        
-        for (node, set) in sim.iter() {
-            let py_set = PySet::new(py, set.iter().map(|node| to_nx_node(py, node)).collect::<PyResult<Vec<_>>>()?)?;
-            map.set_item(to_nx_node(py, node)?, py_set)?;
+    // 能在 Rust 中得出结论时返回结果，需要调用 same_label_fn 时返回 None
+    fn native_label_same(&self, node: &Node, label: &Node) -> Option<bool> {
+        // 原生标签不同就不必再调用比较函数
+        if let (Some(label1), Some(label2)) = (node.label.as_ref(), label.label.as_ref()) {
+            if label1 != label2 {
+                return Some(false);
+            }
+            if self.same_label_fn.is_none() {
+                return Some(true);
+            }
         }
-    
-        Ok(map.into())
-    })
-}
-
-#[pyfunction]
-#[pyo3(signature = (nx_graph1, nx_graph2, compare, is_label_cached = false))]
-pub fn is_simulation_isomorphic_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, compare: Py<PyAny>, is_label_cached: bool) -> PyResult<bool> {
-    let mut graph1 = NetworkXGraph::from_networkx(nx_graph1)?;
-    let graph2 = NetworkXGraph::from_networkx(nx_graph2)?;
-    
-    graph1.register_compare_fn(compare);
+        match self.same_label_fn {
+            Some(_) => None,
+            None => Some(native_same_label_fn(&node.attributes, &label.attributes)),
+        }
+    }
 
-    if is_label_cached {
-        graph1.build_compare_cache(&graph2);
+    // 每个节点的属性最多转换一次 dict，回调分批执行
+    fn compare_in_batches<'n>(
+        &self,
+        py: Python<'_>,
+        other: &NetworkXGraph,
+        candidates: impl Iterator<Item = (&'n Node, &'n Node)>,
+    ) -> PyResult<HashSet<(usize, usize)>> {
+        let compare_fn = self.same_label_fn.as_ref().unwrap().bind(py);
+        let mut attrs1 = vec![None; self.nodes.len()];
+        let mut attrs2 = vec![None; other.nodes.len()];
+
+        let mut cache = HashSet::new();
+        let mut batch = Vec::with_capacity(COMPARE_BATCH_SIZE);
+        let mut pairs = candidates.peekable();
+        while pairs.peek().is_some() {
+            batch.clear();
+            batch.extend(pairs.by_ref().take(COMPARE_BATCH_SIZE));
+            for &(node1, node2) in &batch {
+                let same = match self.native_label_same(node1, node2) {
+                    Some(same) => same,
+                    None => {
+                        let attr1 = cached_dict(py, &mut attrs1, node1)?.clone();
+                        let attr2 = cached_dict(py, &mut attrs2, node2)?;
+                        compare_fn.call1((attr1, attr2))
+                            .and_then(compare_result)
+                            .map_err(|err| self.compare_error(py, other, Compared::Nodes(node1.id, node2.id), err))?
+                    }
+                };
+                if same {
+                    cache.insert((node1.id, node2.id));
+                }
+            }
+            py.check_signals()?;
+        }
+        Ok(cache)
     }
 
-    Ok(NetworkXGraph::has_simulation(graph1.get_simulation_inter(&graph2)))
-}
+    // 每一类用第一个节点作代表，带着它的 key 和属性；arcs 是类之间的边，不带属性，平行边只保留一条
+    fn quotient(&self, py: Python<'_>, classes: &[usize], arcs: impl IntoIterator<Item = (usize, usize)>) -> PyResult<NetworkXGraph> {
+        let mut quotient = NetworkXGraph::py_new(self.kind.is_directed(), false);
+        let mut representatives = Vec::new();
+        for (v, &class) in classes.iter().enumerate() {
+            if class == representatives.len() {
+                representatives.push(v);
+                let attributes = self.nodes[v].attributes.to_py_dict(py)?.extract()?;
+                quotient.add_node(self.node_keys[v].bind(py), Some(attributes))?;
+            }
+        }
+        for (source, target) in arcs {
+            let source = self.node_keys[representatives[source]].bind(py);
+            let target = self.node_keys[representatives[target]].bind(py);
+            quotient.add_edge(source, target, None, None)?;
+        }
+        Ok(quotient)
+    }
 
-#[pyfunction]
-#[pyo3(signature = (nx_graph1, nx_graph2, node_compare, edge_compare, is_label_cached = false))]
-pub fn is_simulation_isomorphic_of_node_edge_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, node_compare: Py<PyAny>, edge_compare: Py<PyAny>, is_label_cached: bool) -> PyResult<bool> {
-    let mut graph1 = NetworkXGraph::from_networkx(nx_graph1)?;
-    let graph2 = NetworkXGraph::from_networkx(nx_graph2)?;
-    
-    graph1.register_compare_fn(node_compare);
-    graph1.register_edge_compare_fn(edge_compare);
+    // 每个节点在 other 中得分不为 0 的节点及其分数；原生标签不同的节点对不调用 compare
+    fn label_scores(&self, py: Python<'_>, other: &NetworkXGraph, compare: &Bound<'_, PyAny>) -> PyResult<Vec<HashMap<usize, f64>>> {
+        let mut attrs2 = vec![None; other.nodes.len()];
+        let mut scores = Vec::with_capacity(self.nodes.len());
+        for node1 in &self.nodes {
+            let attr1 = node1.attributes.to_py_dict(py)?;
+            let mut row = HashMap::new();
+            for node2 in &other.nodes {
+                if let (Some(label1), Some(label2)) = (node1.label.as_ref(), node2.label.as_ref()) {
+                    if label1 != label2 {
+                        continue;
+                    }
+                }
+                let attr2 = cached_dict(py, &mut attrs2, node2)?;
+                let score = compare.call1((&attr1, attr2))
+                    .and_then(score_result)
+                    .map_err(|err| self.compare_error(py, other, Compared::Nodes(node1.id, node2.id), err))?;
+                if score > 0.0 {
+                    row.insert(node2.id, score);
+                }
+            }
+            scores.push(row);
+            py.check_signals()?;
+        }
+        Ok(scores)
+    }
 
-    if is_label_cached {
-        graph1.build_compare_cache(&graph2);
+    // 标签相容的节点归为一类，按第一个节点出现的顺序编号；有 compare 时要求两个方向都相容
+    fn label_classes(&self, py: Python<'_>) -> PyResult<Vec<usize>> {
+        let compare = |a: &Node, b: &Node| -> PyResult<bool> {
+            let compare_fn = self.same_label_fn.as_ref().unwrap().bind(py);
+            compare_fn.call1((a.attributes.to_py_dict(py)?, b.attributes.to_py_dict(py)?))
+                .and_then(compare_result)
+                .map_err(|err| self.compare_error(py, self, Compared::Nodes(a.id, b.id), err))
+        };
+        let mut representatives: Vec<usize> = Vec::new();
+        let mut buckets: HashMap<(Option<&AttrValue>, Option<&Attributes>), Vec<usize>> = HashMap::new();
+        let mut classes = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            // 没有原生标签也没有 compare 时，属性完全相同的节点才是一类
+            let attributes = (node.label.is_none() && self.same_label_fn.is_none()).then_some(&node.attributes);
+            let bucket = buckets.entry((node.label.as_ref(), attributes)).or_default();
+            let mut found = None;
+            for &class in bucket.iter() {
+                let representative = &self.nodes[representatives[class]];
+                if self.same_label_fn.is_none() || (compare(representative, node)? && compare(node, representative)?) {
+                    found = Some(class);
+                    break;
+                }
+            }
+            let class = found.unwrap_or_else(|| {
+                representatives.push(node.id);
+                bucket.push(representatives.len() - 1);
+                representatives.len() - 1
+            });
+            classes.push(class);
+        }
+        Ok(classes)
     }
 
-    Ok(NetworkXGraph::has_simulation(graph1.get_simulation_of_node_edge(&graph2)))
-}
+    fn set_label_source(&mut self, py: Python<'_>, source: &LabelSource) -> PyResult<()> {
+        match source {
+            LabelSource::Keys(keys) => self.set_label_keys(py, keys.clone()),
+            LabelSource::KeyFn(key_fn) => self.register_label_key_fn(py, key_fn.clone_ref(py)),
+        }
+    }
 
-#[pyfunction]
-#[pyo3(signature = (nx_graph1, nx_graph2, node_edge_compare, is_label_cached = false))]
-pub fn is_simulation_isomorphic_of_edge_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, node_edge_compare: Py<PyAny>, is_label_cached: bool) -> PyResult<bool> {
-    let mut graph1 = NetworkXGraph::from_networkx(nx_graph1)?;
-    let graph2 = NetworkXGraph::from_networkx(nx_graph2)?;
-    
-    graph1.register_node_edge_compare_fn(node_edge_compare);
+    // 缺失的属性与 Python 中 attrs.get(key) 一样视为 None
+    fn project_label(&self, py: Python<'_>, attributes: &Attributes) -> PyResult<Option<AttrValue>> {
+        match &self.label_source {
+            None => Ok(None),
+            Some(LabelSource::Keys(keys)) => Ok(Some(AttrValue::List(keys.iter()
+                .map(|key| attributes.0.get(key).cloned().unwrap_or(AttrValue::None))
+                .collect()))),
+            Some(LabelSource::KeyFn(key_fn)) => {
+                let key = key_fn.bind(py).call1((attributes.to_py_dict(py)?,))?;
+                // 与 dict 的键一样必须可哈希
+                key.hash()?;
+                AttrValue::extract(&key).map(Some)
+            }
+        }
+    }
 
-    if is_label_cached {
-        graph1.build_compare_cache(&graph2);
+    fn relabel(&mut self, py: Python<'_>) -> PyResult<()> {
+        let labels = self.nodes.iter()
+            .map(|node| self.project_label(py, &node.attributes))
+            .collect::<PyResult<Vec<_>>>()?;
+        for (node, label) in self.nodes.iter_mut().zip(labels) {
+            node.label = label;
+        }
+        self.same_label_cache = None;
+        self.revision = next_revision();
+        Ok(())
     }
 
-    Ok(NetworkXGraph::has_simulation(graph1.get_simulation_of_edge(&graph2)))
-}
+    // 两图的节点都有原生标签时，按标签把 other 的节点分组
+    fn label_buckets<'b>(&self, other: &'b NetworkXGraph) -> Option<HashMap<&'b AttrValue, Vec<&'b Node>>> {
+        if self.nodes.iter().any(|node| node.label.is_none()) {
+            return None;
+        }
+        let mut buckets: HashMap<_, Vec<_>> = HashMap::new();
+        for node in &other.nodes {
+            buckets.entry(node.label.as_ref()?).or_default().push(node);
+        }
+        Some(buckets)
+    }
 
-impl Directed for NetworkXGraph {}
+    fn adjacency(&self) -> &AdjacencyIndex {
+        self.adjacency.get_or_init(|| {
+            let pairs: Vec<_> = self.edges.iter().map(|edge| (edge.source, edge.target)).collect();
+            AdjacencyIndex::new(self.nodes.len(), &pairs)
+        })
+    }
 
-impl Adjacency<'_> for NetworkXGraph {}
+    fn new() -> Self {
+        <NetworkXGraph as Graph>::new()
+    }
 
-impl AdjacencyInv<'_> for NetworkXGraph {}
+    // 删除一条原边，连同它的镜像边
+    fn remove_edge_at(&mut self, index: usize) {
+        let first = if index > 0 && self.edges[index - 1].mirror { index - 1 } else { index };
+        self.edges.drain(first..=index);
+        self.reindex_edges();
+    }
 
-impl<'a> Degree<'a> for NetworkXGraph {
-    fn get_out_degree(&'a self) -> DegreeList<'a, Self> {
-        let mut degree_map = HashMap::new();
-        
-        for node in &self.nodes {
-            let out_degree = self.edges.iter()
-                .filter(|edge| edge.source == node.id)
-                .count();
-            degree_map.insert(node, out_degree);
+    fn remove_node_at(&mut self, index: usize) {
+        let shift = |node: usize| if node > index { node - 1 } else { node };
+        self.edges.retain(|edge| edge.source != index && edge.target != index);
+        for edge in &mut self.edges {
+            edge.source = shift(edge.source);
+            edge.target = shift(edge.target);
         }
-        
-        // 使用 unsafe 代码来创建 DegreeList，因为其字段是私有的
-        unsafe { std::mem::transmute::<HashMap<&'a Self::Node, usize>, DegreeList<'a, Self>>(degree_map) }
+        self.nodes.remove(index);
+        for node in &mut self.nodes[index..] {
+            node.id -= 1;
+        }
+        self.node_keys.remove(index);
+        self.node_indices.retain(|_, node| *node != index);
+        for node in self.node_indices.values_mut() {
+            *node = shift(*node);
+        }
+        self.bound_values = self.bound_values.drain()
+            .filter(|&(node, _)| node != index)
+            .map(|(node, bound)| (shift(node), bound))
+            .collect();
+        self.edge_bounds = self.edge_bounds.drain()
+            .filter(|&((u, v), _)| u != index && v != index)
+            .map(|((u, v), bound)| ((shift(u), shift(v)), bound))
+            .collect();
+        self.revision = next_revision();
+        self.same_label_cache = None;
+        self.reindex_edges();
     }
 
-    fn get_in_degree(&'a self) -> DegreeList<'a, Self> {
-        let mut degree_map = HashMap::new();
-        
-        for node in &self.nodes {
-            let in_degree = self.edges.iter()
-                .filter(|edge| edge.target == node.id)
-                .count();
-            degree_map.insert(node, in_degree);
+    // 边下标变化后重建 simple_edges，并让邻接索引在下次使用时重建
+    fn reindex_edges(&mut self) {
+        self.simple_edges.clear();
+        if !self.kind.is_multigraph() {
+            for (index, edge) in self.edges.iter().enumerate().filter(|(_, edge)| !edge.mirror) {
+                let pair = if self.kind.is_directed() {
+                    (edge.source, edge.target)
+                } else {
+                    (edge.source.min(edge.target), edge.source.max(edge.target))
+                };
+                self.simple_edges.insert(pair, index);
+            }
         }
-        
-        // 使用 unsafe 代码来创建 DegreeList，因为其字段是私有的
-        unsafe { std::mem::transmute::<HashMap<&'a Self::Node, usize>, DegreeList<'a, Self>>(degree_map) }
+        self.adjacency.take();
     }
 
-    fn out_degree(&'a self, degree_list: &DegreeList<'a, Self>, node: &Self::Node) -> usize {
-        // 使用 unsafe 代码访问私有字段
-        unsafe { 
-            let degree_map = &*(degree_list as *const _ as *const HashMap<&'a Self::Node, usize>);
-            *degree_map.get(node).unwrap_or(&0)
+    // 缓存只对构建时的那张图和比较函数有效，不再适用就丢弃，需要时重新构建
+    fn prepare_compare_cache(&mut self, py: Python<'_>, other: &NetworkXGraph, is_label_cached: bool) -> PyResult<()> {
+        if self.label_cache(other.revision).is_none() {
+            self.same_label_cache = None;
+            if is_label_cached {
+                self.build_compare_cache(py, other)?;
+            }
         }
+        Ok(())
+    }
+
+    // 对 revision 为 other 的图、用当前比较函数构建的缓存
+    fn label_cache(&self, other: u64) -> Option<&LabelCache> {
+        self.same_label_cache.as_ref()
+            .filter(|cache| cache.other == other && same_object(&cache.compare, &self.same_label_fn))
     }
 
-    fn in_degree(&'a self, degree_list: &DegreeList<'a, Self>, node: &Self::Node) -> usize {
-        // 使用 unsafe 代码访问私有字段
-        unsafe { 
-            let degree_map = &*(degree_list as *const _ as *const HashMap<&'a Self::Node, usize>);
-            *degree_map.get(node).unwrap_or(&0)
+    fn has_label_source(&self, py: Python<'_>, source: &LabelSource) -> bool {
+        match (&self.label_source, source) {
+            (Some(LabelSource::Keys(current)), LabelSource::Keys(keys)) => current == keys,
+            (Some(LabelSource::KeyFn(current)), LabelSource::KeyFn(key_fn)) => current.bind(py).is(key_fn.bind(py)),
+            _ => false,
         }
     }
-}
+
+    // 每个节点在 other 中标签相容的节点，按节点下标排列
+    fn candidates(&self, other: &NetworkXGraph) -> Vec<HashSet<usize>> {
+        self.nodes.iter()
+            .map(|u| other.nodes.iter().filter(|v| self.compatible(other.revision, u, v)).map(|v| v.id).collect())
+            .collect()
+    }
+
+    // 边转换为 (source, target) 或多重图的 (source, target, key)，镜像边换成原边
+    fn edge_to_py<'py>(&self, py: Python<'py>, edge: usize) -> PyResult<Bound<'py, PyTuple>> {
+        let edge = &self.edges[if self.edges[edge].mirror { edge + 1 } else { edge }];
+        let (source, target) = (self.node_key(py, &self.nodes[edge.source]), self.node_key(py, &self.nodes[edge.target]));
+        match &edge.key {
+            Some(key) => PyTuple::new(py, [source, target, key.into_pyobject(py)?]),
+            None => PyTuple::new(py, [source, target]),
+        }
+    }
+
+    // 去掉平行边后的 (u, u', bound)
+    fn bounded_edges(&self) -> Vec<(usize, usize, usize)> {
+        let pairs: BTreeSet<(usize, usize)> = self.edges.iter().map(|edge| (edge.source, edge.target)).collect();
+        pairs.into_iter()
+            .map(|(u, v)| (u, v, self.get_bound(&self.nodes[u], &self.nodes[v])))
+            .collect()
+    }
+
+    fn node_index(&self, id: &Bound<'_, PyAny>) -> PyResult<usize> {
+        self.node_indices.get(&NodeKey::extract(id)?)
+            .copied()
+            .ok_or_else(|| UnknownNodeError::new_err(format!("unknown node {}", describe(id))))
+    }
+
+    // 用两张图的原始节点描述出错的比较，原始异常作为 __cause__
+    fn compare_error(&self, py: Python<'_>, other: &NetworkXGraph, compared: Compared, cause: PyErr) -> PyErr {
+        let key1 = |id: usize| describe(self.node_keys[id].bind(py));
+        let key2 = |id: usize| describe(other.node_keys[id].bind(py));
+        let message = match compared {
+            Compared::Nodes(u, v) => format!("compare function failed on nodes {} and {}", key1(u), key2(v)),
+            Compared::Edges((u1, u2), (v1, v2)) => format!(
+                "edge compare function failed on edges ({}, {}) and ({}, {})",
+                key1(u1), key1(u2), key2(v1), key2(v2)
+            ),
+            Compared::Scored(u, v) => format!("score function failed on nodes {} and {}", key1(u), key2(v)),
+        };
+        let error = CompareFunctionError::new_err(message);
+        error.set_cause(py, Some(cause));
+        error
+    }
+
+    // 库的 get_simulation_inter 在 other 为空时会 panic，这时每个节点都没有匹配
+    fn simulation_with<'a>(&'a self, py: Python<'_>, other: &'a NetworkXGraph) -> PyResult<HashMap<&'a Node, HashSet<&'a Node>>> {
+        if other.nodes.is_empty() {
+            return Ok(self.nodes.iter().map(|node| (node, HashSet::new())).collect());
+        }
+        self.checked(py, other, || self.get_simulation_inter(other))
+    }
+
+    // 运行一次与 other 比较的算法，并把其间比较回调的第一个错误抛出
+    fn checked<T>(&self, py: Python<'_>, other: &NetworkXGraph, run: impl FnOnce() -> T) -> PyResult<T> {
+        COMPARE_FAILURE.with_borrow_mut(|failure| *failure = None);
+        self.compared_with.store(other.revision, Ordering::Relaxed);
+        let result = run();
+        match COMPARE_FAILURE.with_borrow_mut(Option::take) {
+            Some((compared, cause)) => Err(self.compare_error(py, other, compared, cause)),
+            None => Ok(result),
+        }
+    }
+
+    // 节点对应的原始 networkx 节点
+    fn node_key<'py>(&self, py: Python<'py>, node: &Node) -> Bound<'py, PyAny> {
+        self.node_keys[node.id].bind(py).clone()
+    }
+}
+
+// 函数的图参数可以是 simulation.Graph，也可以是每次调用时再转换的 networkx 图。
+// 调用时传入的比较函数、标签和 bound 只在这次调用中生效，结束时恢复 Graph 原来的设置
+enum GraphArg<'py> {
+    Owned(Box<NetworkXGraph>),
+    Borrowed(PyRefMut<'py, NetworkXGraph>, Box<Overrides>),
+}
+
+// Graph 被这次调用覆盖之前的设置，None 表示没有被覆盖
+#[derive(Default)]
+struct Overrides {
+    same_label_fn: Option<Option<Py<PyAny>>>,
+    same_edge_fn: Option<Option<Py<PyAny>>>,
+    same_node_edge_fn: Option<Option<Py<PyAny>>>,
+    labels: Option<SavedLabels>,
+    bounds: Option<SavedBounds>,
+}
+
+struct SavedBounds {
+    bound_values: HashMap<usize, usize>,
+    edge_bounds: HashMap<(usize, usize), usize>,
+}
+
+// 重新计算标签之前的标签来源、各节点的标签，以及与之对应的 revision 和缓存
+struct SavedLabels {
+    source: Option<LabelSource>,
+    labels: Vec<Option<AttrValue>>,
+    revision: u64,
+    cache: Option<LabelCache>,
+}
+
+impl<'py> GraphArg<'py> {
+    fn extract(obj: &Bound<'py, PyAny>) -> PyResult<Self> {
+        match obj.cast::<NetworkXGraph>() {
+            Ok(graph) => Ok(GraphArg::Borrowed(graph.try_borrow_mut()?, Box::default())),
+            Err(_) => Ok(GraphArg::Owned(Box::new(NetworkXGraph::from_networkx(obj)?))),
+        }
+    }
+
+    // 同一张图作为两个参数时，第二个参数使用它的拷贝；拷贝带着这次调用的设置
+    fn into_owned(mut self) -> NetworkXGraph {
+        match &mut self {
+            GraphArg::Owned(graph) => std::mem::replace(&mut **graph, NetworkXGraph::new()),
+            GraphArg::Borrowed(graph, _) => graph.clone(),
+        }
+    }
+
+    fn overrides(&mut self) -> Option<(&mut NetworkXGraph, &mut Overrides)> {
+        match self {
+            GraphArg::Owned(_) => None,
+            GraphArg::Borrowed(graph, overrides) => Some((graph, overrides)),
+        }
+    }
+
+    fn register_compare_fn(&mut self, compare: Py<PyAny>) {
+        if let Some((graph, overrides)) = self.overrides() {
+            overrides.same_label_fn.get_or_insert_with(|| graph.same_label_fn.take());
+        }
+        self.deref_mut().register_compare_fn(compare);
+    }
+
+    fn register_edge_compare_fn(&mut self, compare: Py<PyAny>) {
+        if let Some((graph, overrides)) = self.overrides() {
+            overrides.same_edge_fn.get_or_insert_with(|| graph.same_edge_fn.take());
+        }
+        self.deref_mut().register_edge_compare_fn(compare);
+    }
+
+    fn register_node_edge_compare_fn(&mut self, compare: Py<PyAny>) {
+        if let Some((graph, overrides)) = self.overrides() {
+            overrides.same_node_edge_fn.get_or_insert_with(|| graph.same_node_edge_fn.take());
+        }
+        self.deref_mut().register_node_edge_compare_fn(compare);
+    }
+
+    fn set_label_source(&mut self, py: Python<'_>, source: &LabelSource) -> PyResult<()> {
+        if let Some((graph, overrides)) = self.overrides() {
+            if overrides.labels.is_none() && !graph.has_label_source(py, source) {
+                overrides.labels = Some(SavedLabels {
+                    source: graph.label_source.take(),
+                    labels: graph.nodes.iter().map(|node| node.label.clone()).collect(),
+                    revision: graph.revision,
+                    cache: graph.same_label_cache.take(),
+                });
+            }
+        }
+        self.deref_mut().set_label_source(py, source)
+    }
+
+    fn set_bound_values(&mut self, py: Python<'_>, bound_fn: Py<PyAny>) -> PyResult<()> {
+        self.save_bounds();
+        self.deref_mut().set_bound_values(py, bound_fn)
+    }
+
+    fn set_edge_bounds(&mut self, bound: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.save_bounds();
+        self.deref_mut().set_edge_bounds(bound)
+    }
+
+    fn save_bounds(&mut self) {
+        if let Some((graph, overrides)) = self.overrides() {
+            overrides.bounds.get_or_insert_with(|| SavedBounds {
+                bound_values: graph.bound_values.clone(),
+                edge_bounds: graph.edge_bounds.clone(),
+            });
+        }
+    }
+
+    fn pair(obj1: &Bound<'py, PyAny>, obj2: &Bound<'py, PyAny>) -> PyResult<(Self, Self)> {
+        let graph1 = GraphArg::extract(obj1)?;
+        let graph2 = if obj2.is(obj1) {
+            GraphArg::Owned(Box::new((*graph1).clone()))
+        } else {
+            GraphArg::extract(obj2)?
+        };
+        Ok((graph1, graph2))
+    }
+}
+
+impl Deref for GraphArg<'_> {
+    type Target = NetworkXGraph;
+
+    fn deref(&self) -> &NetworkXGraph {
+        match self {
+            GraphArg::Owned(graph) => graph,
+            GraphArg::Borrowed(graph, _) => graph,
+        }
+    }
+}
+
+impl DerefMut for GraphArg<'_> {
+    fn deref_mut(&mut self) -> &mut NetworkXGraph {
+        match self {
+            GraphArg::Owned(graph) => graph,
+            GraphArg::Borrowed(graph, _) => graph,
+        }
+    }
+}
+
+// 标签没有被覆盖时保留这次调用建立的缓存，它记着自己的比较函数，之后的调用可以继续使用
+impl Drop for GraphArg<'_> {
+    fn drop(&mut self) {
+        let GraphArg::Borrowed(graph, overrides) = self else {
+            return;
+        };
+        let overrides = std::mem::take(&mut **overrides);
+        if let Some(compare) = overrides.same_label_fn {
+            graph.same_label_fn = compare;
+        }
+        if let Some(compare) = overrides.same_edge_fn {
+            graph.same_edge_fn = compare;
+        }
+        if let Some(compare) = overrides.same_node_edge_fn {
+            graph.same_node_edge_fn = compare;
+        }
+        if let Some(saved) = overrides.labels {
+            graph.label_source = saved.source;
+            for (node, label) in graph.nodes.iter_mut().zip(saved.labels) {
+                node.label = label;
+            }
+            graph.revision = saved.revision;
+            graph.same_label_cache = saved.cache;
+        }
+        if let Some(saved) = overrides.bounds {
+            graph.bound_values = saved.bound_values;
+            graph.edge_bounds = saved.edge_bounds;
+        }
+    }
+}
+
+fn label_source(label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<Option<LabelSource>> {
+    match (label_keys, label_key_fn) {
+        (Some(_), Some(_)) => Err(PyValueError::new_err("label_keys and label_key_fn cannot be used together")),
+        (keys, key_fn) => Ok(keys.map(LabelSource::Keys).or(key_fn.map(LabelSource::KeyFn))),
+    }
+}
+
+// 两张图用同一组属性名或同一个 key 函数计算原生标签
+fn apply_labels(
+    py: Python<'_>,
+    graph1: &mut GraphArg<'_>,
+    graph2: &mut GraphArg<'_>,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<()> {
+    if let Some(source) = label_source(label_keys, label_key_fn)? {
+        graph1.set_label_source(py, &source)?;
+        graph2.set_label_source(py, &source)?;
+    }
+    Ok(())
+}
+
+// 将 simulation 结果转换为 {graph1 节点: {graph2 节点}} 的 dict
+fn sim_to_py(py: Python<'_>, graph1: &NetworkXGraph, graph2: &NetworkXGraph, sim: &HashMap<&Node, HashSet<&Node>>) -> PyResult<Py<PyAny>> {
+    let map = PyDict::new(py);
+    for (node, set) in sim.iter() {
+        let py_set = PySet::new(py, set.iter().map(|node| graph2.node_key(py, node)))?;
+        map.set_item(graph1.node_key(py, node), py_set)?;
+    }
+    Ok(map.into())
+}
+
+// 下标形式的 relation 转换为 {graph1 节点: {graph2 节点}} 的 dict
+fn relation_to_py<'py>(py: Python<'py>, graph1: &NetworkXGraph, graph2: &NetworkXGraph, relation: &[HashSet<usize>]) -> PyResult<Bound<'py, PyDict>> {
+    let map = PyDict::new(py);
+    for (u, sim_u) in relation.iter().enumerate() {
+        let py_set = PySet::new(py, sim_u.iter().map(|&v| graph2.node_keys[v].bind(py)))?;
+        map.set_item(graph1.node_keys[u].bind(py), py_set)?;
+    }
+    Ok(map)
+}
+
+fn strong_match_to_py<'py>(py: Python<'py>, graph1: &NetworkXGraph, graph2: &NetworkXGraph, matched: &StrongMatch) -> PyResult<Bound<'py, PyDict>> {
+    let nodes: HashSet<usize> = matched.relation.iter().flatten().copied().collect();
+    // 无向图的一条边可能同时以两个方向出现
+    let edges: BTreeSet<usize> = matched.edges.iter()
+        .map(|&edge| if graph2.edges[edge].mirror { edge + 1 } else { edge })
+        .collect();
+
+    let result = PyDict::new(py);
+    result.set_item("center", graph2.node_keys[matched.center].bind(py))?;
+    result.set_item("relation", relation_to_py(py, graph1, graph2, &matched.relation)?)?;
+    result.set_item("nodes", PySet::new(py, nodes.iter().map(|&v| graph2.node_keys[v].bind(py)))?)?;
+    let edges = edges.iter().map(|&edge| graph2.edge_to_py(py, edge)).collect::<PyResult<Vec<_>>>()?;
+    result.set_item("edges", PyList::new(py, edges)?)?;
+    Ok(result)
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn get_simulation_inter(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.simulation_with(nx_graph1.py(), &graph2)?;
+    
+
+    // Convert simulation to a dict from nodes of graph1 to sets of nodes of graph2
+    sim_to_py(nx_graph1.py(), &graph1, &graph2, &sim)
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn is_simulation_isomorphic(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<bool> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.simulation_with(nx_graph1.py(), &graph2)?;
+    Ok(NetworkXGraph::has_simulation(sim))
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, compare, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn get_simulation_inter_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, compare: Py<PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    graph1.register_compare_fn(compare);
+    
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.simulation_with(nx_graph1.py(), &graph2)?;
+
+    sim_to_py(nx_graph1.py(), &graph1, &graph2, &sim)
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, compare, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn is_simulation_isomorphic_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, compare: Py<PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<bool> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+    
+    graph1.register_compare_fn(compare);
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.simulation_with(nx_graph1.py(), &graph2)?;
+    Ok(NetworkXGraph::has_simulation(sim))
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, node_compare, edge_compare, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn get_simulation_of_node_edge_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, node_compare: Py<PyAny>, edge_compare: Py<PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    graph1.register_compare_fn(node_compare);
+    graph1.register_edge_compare_fn(edge_compare);
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.checked(nx_graph1.py(), &graph2, || graph1.get_simulation_of_node_edge(&graph2))?;
+
+    sim_to_py(nx_graph1.py(), &graph1, &graph2, &sim)
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, node_compare, edge_compare, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn is_simulation_isomorphic_of_node_edge_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, node_compare: Py<PyAny>, edge_compare: Py<PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<bool> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+    
+    graph1.register_compare_fn(node_compare);
+    graph1.register_edge_compare_fn(edge_compare);
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.checked(nx_graph1.py(), &graph2, || graph1.get_simulation_of_node_edge(&graph2))?;
+    Ok(NetworkXGraph::has_simulation(sim))
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, node_edge_compare, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn get_simulation_of_edge_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, node_edge_compare: Py<PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    graph1.register_node_edge_compare_fn(node_edge_compare);
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.checked(nx_graph1.py(), &graph2, || graph1.get_simulation_of_edge(&graph2))?;
+
+    sim_to_py(nx_graph1.py(), &graph1, &graph2, &sim)
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, node_edge_compare, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn is_simulation_isomorphic_of_edge_fn(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, node_edge_compare: Py<PyAny>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<bool> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+    
+    graph1.register_node_edge_compare_fn(node_edge_compare);
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let sim = graph1.checked(nx_graph1.py(), &graph2, || graph1.get_simulation_of_edge(&graph2))?;
+    Ok(NetworkXGraph::has_simulation(sim))
+}
+
+// 比较回调在主线程完成，之后的不动点迭代释放 GIL
+fn dual_simulation(py: Python<'_>, graph1: &NetworkXGraph, graph2: &NetworkXGraph) -> PyResult<Vec<HashSet<usize>>> {
+    let candidates = graph1.checked(py, graph2, || graph1.candidates(graph2))?;
+    let (adjacency1, adjacency2) = (graph1.adjacency(), graph2.adjacency());
+    Ok(py.detach(|| dual_refine(adjacency1, adjacency2, candidates)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, compare = None, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn get_dual_simulation(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, compare: Option<Py<PyAny>>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    if let Some(compare) = compare {
+        graph1.register_compare_fn(compare);
+    }
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let relation = dual_simulation(nx_graph1.py(), &graph1, &graph2)?;
+    Ok(relation_to_py(nx_graph1.py(), &graph1, &graph2, &relation)?.into())
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, compare = None, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn is_dual_simulation_isomorphic(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, compare: Option<Py<PyAny>>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<bool> {
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    if let Some(compare) = compare {
+        graph1.register_compare_fn(compare);
+    }
+
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
+
+    let relation = dual_simulation(nx_graph1.py(), &graph1, &graph2)?;
+    Ok(relation.iter().all(|sim_u| !sim_u.is_empty()))
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, compare = None, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn get_strong_simulation(nx_graph1: &Bound<'_, PyAny>, nx_graph2: &Bound<'_, PyAny>, compare: Option<Py<PyAny>>, is_label_cached: bool, label_keys: Option<Vec<String>>, label_key_fn: Option<Py<PyAny>>) -> PyResult<Py<PyAny>> {
+    let py = nx_graph1.py();
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(py, &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    if let Some(compare) = compare {
+        graph1.register_compare_fn(compare);
+    }
+
+    graph1.prepare_compare_cache(py, &graph2, is_label_cached)?;
+
+    let candidates = graph1.checked(py, &graph2, || graph1.candidates(&graph2))?;
+    let (query, data) = (graph1.adjacency(), graph2.adjacency());
+    let matches = py.detach(|| strong_simulation(query, data, candidates))
+        .ok_or_else(|| PyValueError::new_err("strong simulation needs a connected query graph"))?;
+
+    let result = matches.iter()
+        .map(|matched| strong_match_to_py(py, &graph1, &graph2, matched))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(PyList::new(py, result)?.into())
+}
+
+// 无向图在加边时已经展开成对称的有向边，因此所有图都可以按有向图处理
+impl Directed for NetworkXGraph {}
+
+// 邻居和度数都从 CSR 索引中读取，算法传入的 AdjacencyList / DegreeList 不会被使用。
+// 但 graph-base 的 AdjacencyList / DegreeList 字段私有且没有构造函数，无法覆盖默认的
+// get_adj / get_adj_inv / get_out_degree，库每次调用时仍会按节点建一次 HashMap
+impl<'a> Adjacency<'a> for NetworkXGraph {
+    fn get_post(&'a self, _adj: &AdjacencyList<'a, Self>, node: &Self::Node) -> impl Iterator<Item = &'a Self::Node> {
+        self.adjacency().successors(node.id).iter().map(|&id| &self.nodes[id])
+    }
+}
+
+impl<'a> AdjacencyInv<'a> for NetworkXGraph {
+    fn get_pre(&'a self, _adj_inv: &AdjacencyList<'a, Self>, node: &Self::Node) -> impl Iterator<Item = &'a Self::Node> {
+        self.adjacency().predecessors(node.id).iter().map(|&id| &self.nodes[id])
+    }
+}
+
+impl<'a> Degree<'a> for NetworkXGraph {
+    fn out_degree(&'a self, _degree_list: &DegreeList<'a, Self>, node: &Self::Node) -> usize {
+        self.adjacency().out_degree(node.id)
+    }
+
+    fn in_degree(&'a self, _degree_list: &DegreeList<'a, Self>, node: &Self::Node) -> usize {
+        self.adjacency().in_degree(node.id)
+    }
+}
 
 impl<'a> Bounded<'a> for NetworkXGraph {
+    // 边 (u, v) 自己的 bound 优先，其次是 u 节点的 bound，都没有时为 0
     fn get_bound(&'a self, u: &'a Self::Node, v: &'a Self::Node) -> usize {
-        // 从 u 到 v 的 bound 值。我们定义为 u 节点的 bound_values 中的值
-        // 或者可以定义为基于 (u, v) 对的某个函数
-        *self.bound_values.get(&u.id).unwrap_or(&0)
+        self.edge_bounds.get(&(u.id, v.id))
+            .or_else(|| self.bound_values.get(&u.id))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+// "*" 表示不限长度，其余无法转换为非负 int 的值与原来一样视为 0
+fn parse_bound(value: &Bound<'_, PyAny>) -> usize {
+    if value.extract::<String>().is_ok_and(|text| text == "*") {
+        return UNBOUNDED;
     }
+    value.extract::<usize>().unwrap_or(0)
 }
 
 #[pyfunction]
-#[pyo3(signature = (nx_graph1, nx_graph2, compare, bound, is_label_cached = false))]
+#[pyo3(signature = (nx_graph1, nx_graph2, compare, threshold = 0.5, max_iterations = 100, label_keys = None, label_key_fn = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn get_fractional_simulation(
+    nx_graph1: &Bound<'_, PyAny>,
+    nx_graph2: &Bound<'_, PyAny>,
+    compare: &Bound<'_, PyAny>,
+    threshold: f64,
+    max_iterations: usize,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    let py = nx_graph1.py();
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(py, &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+
+    // 1. 用 compare 给每个节点对打出 [0, 1] 的标签分数
+    let label = graph1.label_scores(py, &graph2, compare)?;
+
+    // 2. 沿查询边传播分数直到收敛
+    let (query, data) = (graph1.adjacency(), graph2.adjacency());
+    let scores = py.detach(|| fractional_simulation(query, data, &label, FRACTIONAL_TOLERANCE, max_iterations));
+
+    // 3. 只保留分数不低于 threshold 的节点对
+    let result = PyDict::new(py);
+    for (u, scores_u) in scores.iter().enumerate() {
+        let matches = PyDict::new(py);
+        for (&v, &score) in scores_u.iter().filter(|(_, &score)| score >= threshold) {
+            matches.set_item(graph2.node_keys[v].bind(py), score)?;
+        }
+        result.set_item(graph1.node_keys[u].bind(py), matches)?;
+    }
+    Ok(result.into())
+}
+
+// 一轮中所有分数的变化都不超过它时视为收敛
+const FRACTIONAL_TOLERANCE: f64 = 1e-9;
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, compare, bound = None, is_label_cached = false, label_keys = None, label_key_fn = None, edge_bound = None, return_witnesses = false))]
+#[allow(clippy::too_many_arguments)]
 pub fn get_bounded_simulation(
     nx_graph1: &Bound<'_, PyAny>, 
     nx_graph2: &Bound<'_, PyAny>, 
     compare: Py<PyAny>,
-    bound: Py<PyAny>,
-    is_label_cached: bool
+    bound: Option<Py<PyAny>>,
+    is_label_cached: bool,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+    edge_bound: Option<&Bound<'_, PyAny>>,
+    return_witnesses: bool,
 ) -> PyResult<Py<PyAny>> {
     // 1. 从 NetworkX 图转换
-    let mut graph1 = NetworkXGraph::from_networkx(nx_graph1)?;
-    let graph2 = NetworkXGraph::from_networkx(nx_graph2)?;
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(nx_graph1.py(), &mut graph1, &mut graph2, label_keys, label_key_fn)?;
     
     // 2. 注册 compare 函数
     graph1.register_compare_fn(compare);
     
-    // 3. 为 graph1 设置 bound 值
-    graph1.set_bound_values(bound);
+    // 3. 为 graph1 设置 bound 值，边上的 bound 覆盖节点上的
+    if let Some(bound) = bound {
+        graph1.set_bound_values(nx_graph1.py(), bound)?;
+    }
+    if let Some(edge_bound) = edge_bound {
+        graph1.set_edge_bounds(edge_bound)?;
+    }
     
     // 4. 构建缓存
-    if is_label_cached {
-        graph1.build_compare_cache(&graph2);
-    }
+    graph1.prepare_compare_cache(nx_graph1.py(), &graph2, is_label_cached)?;
     
     // 5. 执行 bounded simulation
-    let sim = graph1.get_bounded_simulation(&graph2);
-    
+    let py = nx_graph1.py();
+    let sim = graph1.checked(py, &graph2, || graph1.get_bounded_simulation(&graph2))?;
+
     // 6. 转换结果为 Python 对象
-    Python::attach(|py| {
-        let map = PyDict::new(py);
-        
-        for (node, set) in sim.iter() {
-            let py_set = PySet::new(py, set.iter().map(|node| to_nx_node(py, node)).collect::<PyResult<Vec<_>>>()?)?;
-            map.set_item(to_nx_node(py, node)?, py_set)?;
+    let map = PyDict::new(py);
+    for (node, set) in sim.iter() {
+        let py_set = PySet::new(py, set.iter().map(|node| graph2.node_key(py, node)))?;
+        map.set_item(graph1.node_key(py, node), py_set)?;
+    }
+    if !return_witnesses {
+        return Ok(map.into());
+    }
+
+    // 7. 为每条查询边和每个匹配对给出一条界内的最短路径，没有匹配时为空 dict
+    let by_edge = PyDict::new(py);
+    if !sim.is_empty() {
+        let mut relation = vec![HashSet::new(); graph1.nodes.len()];
+        for (node, set) in &sim {
+            relation[node.id] = set.iter().map(|node| node.id).collect();
         }
-    
-        Ok(map.into())
-    })
+        let edges = graph1.bounded_edges();
+        let data = graph2.adjacency();
+        let edge_key = |u: usize, u_next: usize| (graph1.node_keys[u].bind(py), graph1.node_keys[u_next].bind(py));
+        for &(u, u_next, _) in &edges {
+            by_edge.set_item(edge_key(u, u_next), PyDict::new(py))?;
+        }
+        for (e, path) in py.detach(|| witnesses(&edges, data, &relation)) {
+            let (u, u_next, _) = edges[e];
+            let pairs = by_edge.get_item(edge_key(u, u_next))?.expect("every query edge has an entry");
+            let (first, last) = (path[0], path[path.len() - 1]);
+            let keys = PyList::new(py, path.iter().map(|&v| graph2.node_keys[v].bind(py)))?;
+            pairs.set_item((graph2.node_keys[first].bind(py), graph2.node_keys[last].bind(py)), keys)?;
+        }
+    }
+    Ok(PyTuple::new(py, [map.into_any(), by_edge.into_any()])?.into())
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, k, score = None, compare = None, is_label_cached = false, label_keys = None, label_key_fn = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn get_top_k_simulation(
+    nx_graph1: &Bound<'_, PyAny>,
+    nx_graph2: &Bound<'_, PyAny>,
+    k: usize,
+    score: Option<&Bound<'_, PyAny>>,
+    compare: Option<Py<PyAny>>,
+    is_label_cached: bool,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    let py = nx_graph1.py();
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(py, &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+    if let Some(compare) = compare {
+        graph1.register_compare_fn(compare);
+    }
+    graph1.prepare_compare_cache(py, &graph2, is_label_cached)?;
+
+    let candidates = graph1.checked(py, &graph2, || graph1.candidates(&graph2))?;
+    let (query, data) = (graph1.adjacency(), graph2.adjacency());
+
+    // 内置的分数在 Rust 中计算，score 为函数时只对需要排序的候选对调用 score(query_attrs, data_attrs)
+    let ranked = match score {
+        Some(score) if score.is_callable() => {
+            let (mut attrs1, mut attrs2) = (vec![None; graph1.nodes.len()], vec![None; graph2.nodes.len()]);
+            top_k(query, data, &candidates, k, |u, v| {
+                let attr1 = cached_dict(py, &mut attrs1, &graph1.nodes[u])?;
+                let attr2 = cached_dict(py, &mut attrs2, &graph2.nodes[v])?;
+                score.call1((attr1, attr2))
+                    .and_then(|result| result.extract::<f64>())
+                    .map_err(|err| graph1.compare_error(py, &graph2, Compared::Scored(u, v), err))
+            })?
+        }
+        _ => {
+            let name = score.map(|score| score.extract::<String>()).transpose()?;
+            let score_fn = match name.as_deref() {
+                None | Some("neighborhood") => |query, data, candidates: &[HashSet<usize>], u, v| neighborhood_score(query, data, candidates, u, v),
+                Some("degree") => |query, data, _: &[HashSet<usize>], u, v| degree_score(query, data, u, v),
+                Some(other) => return Err(PyValueError::new_err(format!("unknown score {:?}, expected 'neighborhood', 'degree' or a function", other))),
+            };
+            let scored = |u, v| Ok::<_, Infallible>(score_fn(query, data, &candidates, u, v));
+            let Ok(ranked) = py.detach(|| top_k(query, data, &candidates, k, scored));
+            ranked
+        }
+    };
+
+    let result = PyDict::new(py);
+    for (u, matches) in ranked.into_iter().enumerate() {
+        let matches = matches.into_iter()
+            .map(|(v, score)| (graph2.node_keys[v].bind(py), score))
+            .collect::<Vec<_>>();
+        result.set_item(graph1.node_keys[u].bind(py), PyList::new(py, matches)?)?;
+    }
+    Ok(result.into())
+}
+
+#[pyfunction]
+#[pyo3(signature = (nx_graph1, nx_graph2, node, data_node = None, compare = None, is_label_cached = false, label_keys = None, label_key_fn = None))]
+#[allow(clippy::too_many_arguments)]
+pub fn explain_simulation(
+    nx_graph1: &Bound<'_, PyAny>,
+    nx_graph2: &Bound<'_, PyAny>,
+    node: &Bound<'_, PyAny>,
+    data_node: Option<&Bound<'_, PyAny>>,
+    compare: Option<Py<PyAny>>,
+    is_label_cached: bool,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    let py = nx_graph1.py();
+    let (mut graph1, mut graph2) = GraphArg::pair(nx_graph1, nx_graph2)?;
+    apply_labels(py, &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+    if let Some(compare) = compare {
+        graph1.register_compare_fn(compare);
+    }
+    graph1.prepare_compare_cache(py, &graph2, is_label_cached)?;
+    let u = graph1.node_index(node)?;
+    let v = data_node.map(|data_node| graph2.node_index(data_node)).transpose()?;
+
+    let candidates = graph1.checked(py, &graph2, || graph1.candidates(&graph2))?;
+    let (query, data) = (graph1.adjacency(), graph2.adjacency());
+    let (_, removals) = py.detach(|| explained_simulation(query, data, candidates.clone()));
+
+    // 给出数据节点时解释这一对，否则解释 node 每个被删掉的候选
+    if let Some(v) = v {
+        return Ok(removal_chain(py, &graph1, &graph2, &candidates, &removals, u, v)?.into());
+    }
+    let by_candidate = PyDict::new(py);
+    for &v in candidates[u].iter().filter(|&&v| removals.contains_key(&(u, v))) {
+        by_candidate.set_item(graph2.node_keys[v].bind(py), removal_chain(py, &graph1, &graph2, &candidates, &removals, u, v)?)?;
+    }
+    Ok(by_candidate.into())
+}
+
+// 从 (u, v) 开始沿着 cause 一直追到最初的删除；(u, v) 在模拟中时为空列表
+fn removal_chain<'py>(
+    py: Python<'py>,
+    graph1: &NetworkXGraph,
+    graph2: &NetworkXGraph,
+    candidates: &[HashSet<usize>],
+    removals: &Removals,
+    mut u: usize,
+    mut v: usize,
+) -> PyResult<Bound<'py, PyList>> {
+    let key1 = |node: usize| graph1.node_keys[node].bind(py);
+    let key2 = |node: usize| graph2.node_keys[node].bind(py);
+    let chain = PyList::empty(py);
+    loop {
+        let step = PyDict::new(py);
+        step.set_item("pair", (key1(u), key2(v)))?;
+        if !candidates[u].contains(&v) {
+            step.set_item("reason", "label")?;
+            chain.append(step)?;
+            break;
+        }
+        let Some(&Removal { query_next, cause }) = removals.get(&(u, v)) else {
+            break;
+        };
+        step.set_item("reason", "successor")?;
+        step.set_item("edge", (key1(u), key1(query_next)))?;
+        step.set_item("removed", cause.map(|w| (key1(query_next), key2(w))))?;
+        chain.append(step)?;
+        match cause {
+            Some(w) => (u, v) = (query_next, w),
+            None => break,
+        }
+    }
+    Ok(chain)
+}
+
+#[pyfunction]
+#[pyo3(signature = (query, data_graphs, compare = None, label_keys = None, label_key_fn = None, isomorphic = false))]
+pub fn batch_simulation(
+    query: &Bound<'_, PyAny>,
+    data_graphs: &Bound<'_, PyAny>,
+    compare: Option<Py<PyAny>>,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+    isomorphic: bool,
+) -> PyResult<Py<PyList>> {
+    let py = query.py();
+    // 1. 查询图只转换一次；缓存只在与它所属的数据图比较时使用，这里保持不变
+    let mut graph1 = GraphArg::extract(query)?;
+    if let Some(compare) = compare {
+        graph1.register_compare_fn(compare);
+    }
+    let labels = label_source(label_keys, label_key_fn)?;
+    if let Some(labels) = &labels {
+        graph1.set_label_source(py, labels)?;
+    }
+    graph1.adjacency();
+
+    // 2. 逐个转换数据图；需要调用 Python 的比较在这里完成，出错的图只影响自己的结果
+    let prepare = |data: &Bound<'_, PyAny>| -> PyResult<(NetworkXGraph, Option<Vec<HashSet<usize>>>)> {
+        // 查询图本身也在数据图中时，它已经被 graph1 借用，改用转换好的副本
+        let mut graph2 = if data.is(query) {
+            (*graph1).clone()
+        } else {
+            GraphArg::extract(data)?.into_owned()
+        };
+        if let Some(labels) = &labels {
+            graph2.set_label_source(py, labels)?;
+        }
+        graph2.adjacency();
+        let candidates = match graph1.same_label_fn {
+            Some(_) => Some(graph1.checked(py, &graph2, || graph1.candidates(&graph2))?),
+            None => None,
+        };
+        Ok((graph2, candidates))
+    };
+    let prepared: Vec<PyResult<_>> = data_graphs.try_iter()?
+        .map(|data| prepare(&data?))
+        .collect();
+
+    // 3. 各数据图并行计算
+    let graph1 = &*graph1;
+    let relations: Vec<Option<Vec<HashSet<usize>>>> = py.detach(|| prepared.par_iter()
+        .map(|prepared| prepared.as_ref().ok().map(|(graph2, candidates)| {
+            let candidates = candidates.clone().unwrap_or_else(|| graph1.candidates(graph2));
+            maximum_simulation(graph1.adjacency(), graph2.adjacency(), candidates)
+        }))
+        .collect());
+
+    let results = PyList::empty(py);
+    for (prepared, relation) in prepared.into_iter().zip(relations) {
+        match (prepared, relation) {
+            (Ok(_), Some(relation)) if isomorphic => results.append(relation.iter().all(|sim_u| !sim_u.is_empty()))?,
+            (Ok((graph2, _)), Some(relation)) => results.append(relation_to_py(py, graph1, &graph2, &relation)?)?,
+            (Err(err), _) => results.append(err.into_value(py))?,
+            (Ok(_), None) => unreachable!("every prepared graph has a relation"),
+        }
+    }
+    Ok(results.unbind())
+}
+
+#[pyfunction]
+#[pyo3(signature = (graphs, compare = None, label_keys = None, label_key_fn = None, score = false))]
+pub fn simulation_matrix(
+    graphs: &Bound<'_, PyAny>,
+    compare: Option<Py<PyAny>>,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+    score: bool,
+) -> PyResult<Py<PyList>> {
+    let py = graphs.py();
+    // 1. 每张图只转换一次；传入 compare 时所有图都使用它，否则沿用各图注册的
+    let labels = label_source(label_keys, label_key_fn)?;
+    let mut converted = Vec::new();
+    for graph in graphs.try_iter()? {
+        let mut graph = GraphArg::extract(&graph?)?.into_owned();
+        if let Some(compare) = &compare {
+            graph.same_label_fn = Some(compare.clone_ref(py));
+        }
+        if let Some(labels) = &labels {
+            graph.set_label_source(py, labels)?;
+        }
+        graph.adjacency();
+        converted.push(graph);
+    }
+
+    // 2. 属性和标签都相同的节点归为一个标签类，每张图记下自己用到的类
+    let mut distinct: Vec<(usize, usize)> = Vec::new();
+    let mut interned: HashMap<(&Attributes, &Option<AttrValue>), usize> = HashMap::new();
+    let ids: Vec<Vec<usize>> = converted.iter().enumerate()
+        .map(|(g, graph)| graph.nodes.iter()
+            .map(|node| *interned.entry((&node.attributes, &node.label)).or_insert_with(|| {
+                distinct.push((g, node.id));
+                distinct.len() - 1
+            }))
+            .collect())
+        .collect();
+    let by_id: Vec<HashMap<usize, Vec<usize>>> = ids.iter()
+        .map(|ids| {
+            let mut by_id: HashMap<usize, Vec<usize>> = HashMap::new();
+            for (v, &id) in ids.iter().enumerate() {
+                by_id.entry(id).or_default().push(v);
+            }
+            by_id
+        })
+        .collect();
+    // 类按第一次出现的顺序编号，排序后 compare 的调用顺序是确定的
+    let classes: Vec<Vec<usize>> = by_id.iter()
+        .map(|by_id| {
+            let mut classes: Vec<usize> = by_id.keys().copied().collect();
+            classes.sort_unstable();
+            classes
+        })
+        .collect();
+    let node = |id: usize| {
+        let (g, u) = distinct[id];
+        &converted[g].nodes[u]
+    };
+    // 第 i 张图作查询时按它的 compare 判断
+    let native_same = |i: usize, a: usize, b: usize| converted[i].native_label_same(node(a), node(b));
+
+    // 3. 原生标签决定不了的类对调用查询图的 compare；同一个函数对每对类最多调用一次
+    let mut compare_fns: Vec<&Py<PyAny>> = Vec::new();
+    let compare_of: Vec<Option<usize>> = converted.iter()
+        .map(|graph| graph.same_label_fn.as_ref().map(|compare| {
+            compare_fns.iter().position(|known| known.as_ptr() == compare.as_ptr()).unwrap_or_else(|| {
+                compare_fns.push(compare);
+                compare_fns.len() - 1
+            })
+        }))
+        .collect();
+    let count = converted.len();
+    let mut compared: HashMap<(usize, usize, usize), bool> = HashMap::new();
+    // 属性第一次用到时才转换成 dict
+    let mut dicts: Vec<Option<Bound<'_, PyDict>>> = vec![None; distinct.len()];
+    for (i, j) in (0..count).flat_map(|i| (0..count).map(move |j| (i, j))) {
+        let Some(c) = compare_of[i] else { continue };
+        let compare = compare_fns[c].bind(py);
+        for &a in &classes[i] {
+            for &b in &classes[j] {
+                if compared.contains_key(&(c, a, b)) || native_same(i, a, b).is_some() {
+                    continue;
+                }
+                for id in [a, b] {
+                    if dicts[id].is_none() {
+                        dicts[id] = Some(node(id).attributes.to_py_dict(py)?);
+                    }
+                }
+                let (u, v) = (by_id[i][&a][0], by_id[j][&b][0]);
+                let same = compare.call1((dicts[a].as_ref().unwrap(), dicts[b].as_ref().unwrap()))
+                    .and_then(compare_result)
+                    .map_err(|err| converted[i].compare_error(py, &converted[j], Compared::Nodes(u, v), err))?;
+                compared.insert((c, a, b), same);
+            }
+        }
+        py.check_signals()?;
+    }
+
+    // 4. 所有有序图对并行计算，(i, j) 表示第 i 张图能否被第 j 张图模拟；
+    //    标签表只在这一对图用到的类之间建立
+    let matched: Vec<(usize, usize)> = py.detach(|| (0..count * count).into_par_iter()
+        .map(|pair| {
+            let (i, j) = (pair / count, pair % count);
+            let same = |a: usize, b: usize| native_same(i, a, b)
+                .or_else(|| compare_of[i].and_then(|c| compared.get(&(c, a, b)).copied()))
+                == Some(true);
+            let table: HashMap<usize, Vec<usize>> = classes[i].iter()
+                .map(|&a| {
+                    let nodes = by_id[j].iter()
+                        .filter(|&(&b, _)| same(a, b))
+                        .flat_map(|(_, nodes)| nodes.iter().copied())
+                        .collect();
+                    (a, nodes)
+                })
+                .collect();
+            let candidates = ids[i].iter().map(|id| table[id].iter().copied().collect()).collect();
+            let relation = maximum_simulation(converted[i].adjacency(), converted[j].adjacency(), candidates);
+            (relation.iter().filter(|sim_u| !sim_u.is_empty()).count(), relation.len())
+        })
+        .collect());
+
+    let rows = PyList::empty(py);
+    for row in matched.chunks(count.max(1)).take(count) {
+        let row: Vec<Bound<'_, PyAny>> = row.iter()
+            .map(|&(matched, total)| match score {
+                true if total == 0 => 1.0.into_bound_py_any(py),
+                true => (matched as f64 / total as f64).into_bound_py_any(py),
+                false => (matched == total).into_bound_py_any(py),
+            })
+            .collect::<PyResult<_>>()?;
+        rows.append(PyList::new(py, row)?)?;
+    }
+    Ok(rows.unbind())
+}
+
+// 单张图的标签选项与其他函数一致
+fn prepare_single<'py>(
+    graph: &Bound<'py, PyAny>,
+    compare: Option<Py<PyAny>>,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<GraphArg<'py>> {
+    let py = graph.py();
+    let labels = label_source(label_keys, label_key_fn)?;
+    let mut graph = GraphArg::extract(graph)?;
+    if let Some(compare) = compare {
+        graph.register_compare_fn(compare);
+    }
+    if let Some(labels) = &labels {
+        graph.set_label_source(py, labels)?;
+    }
+    Ok(graph)
+}
+
+// 每个节点所属的最大互模拟等价类
+fn bisimulation_of(py: Python<'_>, graph: &NetworkXGraph) -> PyResult<Vec<usize>> {
+    let initial = graph.label_classes(py)?;
+    let adjacency = graph.adjacency();
+    Ok(py.detach(|| refine_classes(adjacency, initial)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (graph, compare = None, label_keys = None, label_key_fn = None))]
+pub fn bisimulation_classes(
+    graph: &Bound<'_, PyAny>,
+    compare: Option<Py<PyAny>>,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    let py = graph.py();
+    let graph = prepare_single(graph, compare, label_keys, label_key_fn)?;
+    let classes = bisimulation_of(py, &graph)?;
+    let result = PyDict::new(py);
+    for (v, class) in classes.into_iter().enumerate() {
+        result.set_item(graph.node_keys[v].bind(py), class)?;
+    }
+    Ok(result.into())
+}
+
+#[pyfunction]
+#[pyo3(signature = (graph, compare = None, label_keys = None, label_key_fn = None))]
+pub fn quotient_graph(
+    graph: &Bound<'_, PyAny>,
+    compare: Option<Py<PyAny>>,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<Py<NetworkXGraph>> {
+    let py = graph.py();
+    let graph = prepare_single(graph, compare, label_keys, label_key_fn)?;
+    let classes = bisimulation_of(py, &graph)?;
+    let arcs = graph.edges.iter()
+        .filter(|edge| !edge.mirror)
+        .map(|edge| (classes[edge.source], classes[edge.target]));
+    Py::new(py, graph.quotient(py, &classes, arcs)?)
+}
+
+#[pyfunction]
+#[pyo3(signature = (query, compare = None, label_keys = None, label_key_fn = None, return_mapping = false))]
+pub fn minimize_query(
+    query: &Bound<'_, PyAny>,
+    compare: Option<Py<PyAny>>,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+    return_mapping: bool,
+) -> PyResult<Py<PyAny>> {
+    let py = query.py();
+    let query = prepare_single(query, compare, label_keys, label_key_fn)?;
+    // 查询图对自身的最大模拟
+    let candidates = query.checked(py, &query, || query.candidates(&query))?;
+    let adjacency = query.adjacency();
+    let minimized = py.detach(|| minimize(adjacency, &maximum_simulation(adjacency, adjacency, candidates)));
+    let graph = Py::new(py, query.quotient(py, &minimized.classes, minimized.arcs.iter().copied())?)?;
+    if !return_mapping {
+        return Ok(graph.into_any());
+    }
+    let mapping = PyDict::new(py);
+    for (u, &class) in minimized.classes.iter().enumerate() {
+        mapping.set_item(query.node_keys[u].bind(py), query.node_keys[minimized.representatives[class]].bind(py))?;
+    }
+    (graph, mapping).into_py_any(py)
+}
+
+#[pyfunction]
+#[pyo3(signature = (q1, q2, compare = None, is_label_cached = false, label_keys = None, label_key_fn = None))]
+pub fn query_contained(
+    q1: &Bound<'_, PyAny>,
+    q2: &Bound<'_, PyAny>,
+    compare: Option<Py<PyAny>>,
+    is_label_cached: bool,
+    label_keys: Option<Vec<String>>,
+    label_key_fn: Option<Py<PyAny>>,
+) -> PyResult<(bool, Option<Py<PyAny>>)> {
+    let py = q1.py();
+    // q2 作查询、q1 作数据，得到 q2 的每个节点被 q1 的哪些节点模拟
+    let (mut graph2, mut graph1) = GraphArg::pair(q2, q1)?;
+    apply_labels(py, &mut graph2, &mut graph1, label_keys, label_key_fn)?;
+    if let Some(compare) = compare {
+        graph2.register_compare_fn(compare);
+    }
+    graph2.prepare_compare_cache(py, &graph1, is_label_cached)?;
+
+    let sim = graph2.simulation_with(py, &graph1)?;
+    let mut witness = vec![HashSet::new(); graph2.nodes.len()];
+    for (node, set) in &sim {
+        witness[node.id] = set.iter().map(|node| node.id).collect::<HashSet<usize>>();
+    }
+    // q1 为空时什么也不匹配，被任何模式包含
+    if !graph1.nodes.is_empty() && !covers(&graph2, &graph1, &witness) {
+        return Ok((false, None));
+    }
+    Ok((true, Some(sim_to_py(py, &graph2, &graph1, &sim)?)))
+}
+
+// q2 的每个节点都被 q1 的节点模拟，且 q1 的每个节点和每条边都被 q2 的某个节点和某条边用到，
+// 这样 q1 匹配到的节点和边也都会被 q2 匹配到
+fn covers(q2: &NetworkXGraph, q1: &NetworkXGraph, witness: &[HashSet<usize>]) -> bool {
+    let simulated = witness.iter().all(|nodes| !nodes.is_empty());
+    let nodes_covered = (0..q1.nodes.len()).all(|u1| witness.iter().any(|nodes| nodes.contains(&u1)));
+    let edges_covered = q1.edges.iter().all(|edge1| {
+        q2.edges.iter().any(|edge2| witness[edge2.source].contains(&edge1.source) && witness[edge2.target].contains(&edge1.target))
+    });
+    simulated && nodes_covered && edges_covered
+}
+
+/// The maximum simulation of a query in a data graph, kept up to date while
+/// the data graph changes.
+///
+/// The session works on its own copies of both graphs. Every update returns
+/// the `(query node, data node)` pairs it added to and removed from the
+/// relation.
+#[pyclass]
+pub struct SimulationSession {
+    query: NetworkXGraph,
+    data: NetworkXGraph,
+    relation: Vec<HashSet<usize>>,
+}
+
+// 一次更新前后 relation 的差，先删后加的节点对互相抵消
+#[derive(Default)]
+struct Changes {
+    added: HashSet<(usize, usize)>,
+    removed: HashSet<(usize, usize)>,
+}
+
+impl Changes {
+    fn add(&mut self, pair: (usize, usize)) {
+        if !self.removed.remove(&pair) {
+            self.added.insert(pair);
+        }
+    }
+
+    fn remove(&mut self, pair: (usize, usize)) {
+        if !self.added.remove(&pair) {
+            self.removed.insert(pair);
+        }
+    }
+}
+
+#[pymethods]
+impl SimulationSession {
+    #[new]
+    #[pyo3(signature = (query, data, compare = None, is_label_cached = false, label_keys = None, label_key_fn = None))]
+    fn py_new(
+        query: &Bound<'_, PyAny>,
+        data: &Bound<'_, PyAny>,
+        compare: Option<Py<PyAny>>,
+        is_label_cached: bool,
+        label_keys: Option<Vec<String>>,
+        label_key_fn: Option<Py<PyAny>>,
+    ) -> PyResult<Self> {
+        let py = query.py();
+        let (mut graph1, mut graph2) = GraphArg::pair(query, data)?;
+        apply_labels(py, &mut graph1, &mut graph2, label_keys, label_key_fn)?;
+        if let Some(compare) = compare {
+            graph1.register_compare_fn(compare);
+        }
+        graph1.prepare_compare_cache(py, &graph2, is_label_cached)?;
+        let (query, data) = (graph1.into_owned(), graph2.into_owned());
+
+        let candidates = query.checked(py, &data, || query.candidates(&data))?;
+        let relation = py.detach(|| maximum_simulation(query.adjacency(), data.adjacency(), candidates));
+        Ok(SimulationSession { query, data, relation })
+    }
+
+    fn relation<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        relation_to_py(py, &self.query, &self.data, &self.relation)
+    }
+
+    // 两端节点必须已经存在
+    #[pyo3(signature = (source, target, key = None, **attributes))]
+    fn insert_edge<'py>(
+        &mut self,
+        py: Python<'py>,
+        source: &Bound<'py, PyAny>,
+        target: &Bound<'py, PyAny>,
+        key: Option<&Bound<'py, PyAny>>,
+        attributes: Option<HashMap<String, Bound<'py, PyAny>>>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let (s, t) = (self.data.node_index(source)?, self.data.node_index(target)?);
+        // 已有同方向的边时只是平行边或属性更新，relation 不变
+        let existed = self.data.adjacency().successors(s).contains(&t);
+        self.data.add_edge(source, target, key, attributes)?;
+        if existed {
+            return self.changes_to_py(py, Changes::default());
+        }
+
+        let seeds = if self.data.kind.is_directed() { vec![s] } else { vec![s, t] };
+        let area = ancestors(self.data.adjacency(), &seeds);
+        let grown = match self.query.checked(py, &self.data, || self.compatible_pairs(&area, self.data.revision)) {
+            Ok(grown) => grown,
+            Err(err) => {
+                self.data.remove_edge_at(self.data.edges.len() - 1);
+                return Err(err);
+            }
+        };
+        let changes = self.apply(Vec::new(), Vec::new(), grown);
+        self.changes_to_py(py, changes)
+    }
+
+    #[pyo3(signature = (source, target, key = None))]
+    fn delete_edge<'py>(
+        &mut self,
+        py: Python<'py>,
+        source: &Bound<'py, PyAny>,
+        target: &Bound<'py, PyAny>,
+        key: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let (s, t) = (self.data.node_index(source)?, self.data.node_index(target)?);
+        self.data.remove_edge(source, target, key)?;
+        let arcs = if self.data.kind.is_directed() { vec![(s, t)] } else { vec![(s, t), (t, s)] };
+        let mut pending = Vec::new();
+        for (s, t) in arcs {
+            // 还有平行边时 s 的后继不变
+            if self.data.adjacency().successors(s).contains(&t) {
+                continue;
+            }
+            pending.extend((0..self.relation.len()).filter(|&u| self.relation[u].contains(&s)).map(|u| (u, s)));
+        }
+        let changes = self.apply(Vec::new(), pending, Vec::new());
+        self.changes_to_py(py, changes)
+    }
+
+    // 与 Graph.add_node 一样，节点已存在时更新它的属性
+    #[pyo3(signature = (id, **attributes))]
+    fn insert_node<'py>(
+        &mut self,
+        py: Python<'py>,
+        id: &Bound<'py, PyAny>,
+        attributes: Option<HashMap<String, Bound<'py, PyAny>>>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let existing = self.data.node_indices.get(&NodeKey::extract(id)?).copied();
+        let saved = existing.map(|v| self.data.nodes[v].clone());
+        let before = self.data.revision;
+        self.data.add_node(id, attributes)?;
+        let v = existing.unwrap_or(self.data.nodes.len() - 1);
+
+        // 比较回调都在修改 relation 之前完成，失败时把数据图恢复原样
+        let compared = self.query.checked(py, &self.data, || {
+            let mut area = ancestors(self.data.adjacency(), &[v]);
+            area.remove(&v);
+            (self.compatible_with(v), self.compatible_pairs(&area, before))
+        });
+        let (compatible, mut grown) = match compared {
+            Ok(compared) => compared,
+            Err(err) => {
+                match saved {
+                    Some(node) => self.data.nodes[v] = node,
+                    None => self.data.remove_node_at(v),
+                }
+                self.data.revision = before;
+                return Err(err);
+            }
+        };
+        if let Some(cache) = self.query.same_label_cache.as_mut().filter(|cache| cache.other == before) {
+            cache.pairs.retain(|&(_, w)| w != v);
+            cache.pairs.extend(compatible.iter().map(|&u| (u, v)));
+            cache.other = self.data.revision;
+        }
+
+        let dropped = (0..self.relation.len())
+            .filter(|&u| self.relation[u].contains(&v) && !compatible.contains(&u))
+            .map(|u| (u, v))
+            .collect();
+        grown.extend(compatible.iter().map(|&u| (u, v)));
+        let changes = self.apply(dropped, Vec::new(), grown);
+        self.changes_to_py(py, changes)
+    }
+
+    fn delete_node<'py>(&mut self, py: Python<'py>, id: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyTuple>> {
+        let v = self.data.node_index(id)?;
+        let dropped = (0..self.relation.len())
+            .filter(|&u| self.relation[u].contains(&v))
+            .map(|u| (u, v))
+            .collect();
+        let changes = self.apply(dropped, Vec::new(), Vec::new());
+        // 节点的原始 key 在删除前转换
+        let result = self.changes_to_py(py, changes)?;
+
+        let before = self.data.revision;
+        self.data.remove_node_at(v);
+        let shift = |w: usize| if w > v { w - 1 } else { w };
+        for sim_u in &mut self.relation {
+            *sim_u = sim_u.drain().map(shift).collect();
+        }
+        if let Some(cache) = self.query.same_label_cache.as_mut().filter(|cache| cache.other == before) {
+            cache.pairs = cache.pairs.drain().filter(|&(_, w)| w != v).map(|(u, w)| (u, shift(w))).collect();
+            cache.other = self.data.revision;
+        }
+        Ok(result)
+    }
+}
+
+impl SimulationSession {
+    // 先删去 dropped 并检查 pending，再加入 grown 中还不在 relation 里的节点对，只需要检查新加入的对
+    fn apply(&mut self, dropped: Vec<(usize, usize)>, pending: Vec<(usize, usize)>, grown: Vec<(usize, usize)>) -> Changes {
+        let mut changes = Changes::default();
+        let (query, data) = (self.query.adjacency(), self.data.adjacency());
+        for pair in refine_simulation(query, data, &mut self.relation, dropped, pending) {
+            changes.remove(pair);
+        }
+        let grown: Vec<_> = grown.into_iter().filter(|&(u, w)| self.relation[u].insert(w)).collect();
+        for &pair in &grown {
+            changes.add(pair);
+        }
+        for pair in refine_simulation(query, data, &mut self.relation, Vec::new(), grown) {
+            changes.remove(pair);
+        }
+        changes
+    }
+
+    // area 中与查询节点标签相容的全部节点对，已在 relation 中的不需要再比较；
+    // area 中的节点自数据图的 revision 为 revision 起没有变过
+    fn compatible_pairs(&self, area: &HashSet<usize>, revision: u64) -> Vec<(usize, usize)> {
+        self.query.nodes.iter()
+            .flat_map(|u| area.iter().map(move |&w| (u, w)))
+            .filter(|&(u, w)| self.relation[u.id].contains(&w) || self.query.compatible(revision, u, &self.data.nodes[w]))
+            .map(|(u, w)| (u.id, w))
+            .collect()
+    }
+
+    // 标签刚变过的节点不能用缓存，直接比较
+    fn compatible_with(&self, v: usize) -> HashSet<usize> {
+        let node = &self.data.nodes[v];
+        self.query.nodes.iter()
+            .filter(|u| match self.query.native_label_same(u, node) {
+                Some(same) => same,
+                None => self.query.callback_label_same(u, node),
+            })
+            .map(|u| u.id)
+            .collect()
+    }
+
+    fn changes_to_py<'py>(&self, py: Python<'py>, changes: Changes) -> PyResult<Bound<'py, PyTuple>> {
+        let pairs_to_py = |pairs: HashSet<(usize, usize)>| {
+            PySet::new(py, pairs.into_iter().map(|(u, v)| (self.query.node_keys[u].bind(py), self.data.node_keys[v].bind(py))))
+        };
+        PyTuple::new(py, [pairs_to_py(changes.added)?, pairs_to_py(changes.removed)?])
+    }
 }
 
 // 模块定义
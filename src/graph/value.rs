@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+
+/// A node or edge attribute value converted to Rust.
+///
+/// Numbers compare the way Python compares them, so `True == 1 == 1.0`.
+/// Values without a Rust counterpart are kept as Python objects and fall
+/// back to `__eq__` and `__hash__`, also against native numbers, so
+/// `numpy.int64(3)` still equals `3`.
+#[derive(Clone, Debug)]
+pub enum AttrValue {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<AttrValue>),
+    Tuple(Vec<AttrValue>),
+    Dict(BTreeMap<String, AttrValue>),
+    Object(PyValue),
+}
+
+impl AttrValue {
+    pub fn extract(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if obj.is_none() {
+            return Ok(AttrValue::None);
+        }
+        if obj.is_exact_instance_of::<PyBool>() {
+            return Ok(AttrValue::Bool(obj.extract()?));
+        }
+        if obj.is_exact_instance_of::<PyInt>() {
+            if let Ok(value) = obj.extract::<i64>() {
+                return Ok(AttrValue::Int(value));
+            }
+        }
+        if obj.is_exact_instance_of::<PyFloat>() {
+            return Ok(AttrValue::Float(obj.extract()?));
+        }
+        if obj.is_exact_instance_of::<PyString>() {
+            return Ok(AttrValue::Str(obj.extract()?));
+        }
+        if obj.is_exact_instance_of::<PyList>() || obj.is_exact_instance_of::<PyTuple>() {
+            let items = obj.try_iter()?
+                .map(|item| AttrValue::extract(&item?))
+                .collect::<PyResult<Vec<_>>>()?;
+            return Ok(if obj.is_exact_instance_of::<PyList>() { AttrValue::List(items) } else { AttrValue::Tuple(items) });
+        }
+        if let Ok(dict) = obj.cast_exact::<PyDict>() {
+            let mut map = BTreeMap::new();
+            for (key, value) in dict.iter() {
+                let Ok(key) = key.extract::<String>() else {
+                    return Ok(AttrValue::Object(PyValue(obj.clone().unbind())));
+                };
+                map.insert(key, AttrValue::extract(&value)?);
+            }
+            return Ok(AttrValue::Dict(map));
+        }
+        Ok(AttrValue::Object(PyValue(obj.clone().unbind())))
+    }
+
+    // bool 按 0 和 1 参与数值比较，与 Python 中 True == 1 == 1.0 一致
+    fn as_number(&self) -> Option<Number> {
+        match self {
+            AttrValue::Bool(b) => Some(Number::Int(*b as i64)),
+            AttrValue::Int(i) => Some(Number::Int(*i)),
+            AttrValue::Float(f) => Some(Number::Float(*f)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+// 能精确表示为 i64 的整数值浮点数
+fn integral(f: f64) -> Option<i64> {
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;  // 2**63
+    (f.fract() == 0.0 && (-LIMIT..LIMIT).contains(&f)).then_some(f as i64)
+}
+
+impl PartialEq for AttrValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (AttrValue::None, AttrValue::None) => true,
+            (AttrValue::Str(a), AttrValue::Str(b)) => a == b,
+            (AttrValue::List(a), AttrValue::List(b)) => a == b,
+            (AttrValue::Tuple(a), AttrValue::Tuple(b)) => a == b,
+            (AttrValue::Dict(a), AttrValue::Dict(b)) => a == b,
+            (AttrValue::Object(a), AttrValue::Object(b)) => a == b,
+            (AttrValue::Object(a), b) | (b, AttrValue::Object(a)) if b.as_number().is_some() => Python::attach(|py| {
+                b.into_pyobject(py).and_then(|b| a.0.bind(py).eq(b)).unwrap_or(false)
+            }),
+            _ => match (self.as_number(), other.as_number()) {
+                (Some(Number::Int(a)), Some(Number::Int(b))) => a == b,
+                (Some(Number::Float(a)), Some(Number::Float(b))) => a == b || (a.is_nan() && b.is_nan()),
+                (Some(Number::Int(i)), Some(Number::Float(f))) | (Some(Number::Float(f)), Some(Number::Int(i))) => {
+                    // 与 Python 一样精确比较，不转换成 f64：2**53 + 1 != 2.0**53
+                    integral(f) == Some(i)
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+impl Eq for AttrValue {}
+
+impl Hash for AttrValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // 数值与 Python 对象使用 Python 的哈希值，相等的数值对象和原生数值哈希相同
+        match (self, self.as_number()) {
+            (_, Some(Number::Int(i))) => return hash_int(i).hash(state),
+            (_, Some(Number::Float(f))) => return hash_float(f).hash(state),
+            (AttrValue::Object(obj), _) => return obj.hash(state),
+            _ => {}
+        }
+        std::mem::discriminant(self).hash(state);
+        match self {
+            AttrValue::Str(s) => s.hash(state),
+            AttrValue::List(items) | AttrValue::Tuple(items) => items.hash(state),
+            AttrValue::Dict(map) => map.hash(state),
+            _ => {}
+        }
+    }
+}
+
+// Python 对数值取模 2**61 - 1 计算哈希，整数值的浮点数与对应的整数哈希相同
+const HASH_MODULUS: u64 = (1 << 61) - 1;
+
+fn hash_int(i: i64) -> i64 {
+    let hash = (i.unsigned_abs() % HASH_MODULUS) as i64;
+    finish_hash(if i < 0 { -hash } else { hash })
+}
+
+// 浮点数是 mantissa * 2**exponent，按有理数的哈希计算；所有 NaN 相等，因此哈希都为 0
+fn hash_float(f: f64) -> i64 {
+    if f.is_nan() {
+        return 0;
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { 314_159 } else { -314_159 };
+    }
+    let bits = f.to_bits();
+    let (exponent_bits, fraction) = ((bits >> 52) & 0x7ff, bits & ((1 << 52) - 1));
+    let (mantissa, exponent) = match exponent_bits {
+        0 => (fraction, -1074),
+        _ => (fraction | (1 << 52), exponent_bits as i64 - 1075),
+    };
+    // 2**61 ≡ 1，乘以 2**exponent 就是在 61 位内循环左移
+    let shift = exponent.rem_euclid(61) as u32;
+    let x = mantissa % HASH_MODULUS;
+    let x = if shift == 0 { x } else { ((x << shift) & HASH_MODULUS) | (x >> (61 - shift)) };
+    finish_hash(if f < 0.0 { -(x as i64) } else { x as i64 })
+}
+
+// Python 中 -1 表示出错，哈希值为 -1 时用 -2 代替
+fn finish_hash(hash: i64) -> i64 {
+    if hash == -1 { -2 } else { hash }
+}
+
+impl<'py> IntoPyObject<'py> for &AttrValue {
+    type Target = PyAny;
+    type Output = Bound<'py, PyAny>;
+    type Error = PyErr;
+
+    fn into_pyobject(self, py: Python<'py>) -> PyResult<Self::Output> {
+        match self {
+            AttrValue::None => Ok(py.None().into_bound(py)),
+            AttrValue::Bool(b) => b.into_bound_py_any(py),
+            AttrValue::Int(i) => i.into_bound_py_any(py),
+            AttrValue::Float(f) => f.into_bound_py_any(py),
+            AttrValue::Str(s) => s.into_bound_py_any(py),
+            AttrValue::List(items) => PyList::new(py, items)?.into_bound_py_any(py),
+            AttrValue::Tuple(items) => PyTuple::new(py, items)?.into_bound_py_any(py),
+            AttrValue::Dict(map) => {
+                let dict = PyDict::new(py);
+                for (key, value) in map {
+                    dict.set_item(key, value)?;
+                }
+                Ok(dict.into_any())
+            }
+            AttrValue::Object(obj) => Ok(obj.0.bind(py).clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for AttrValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttrValue::None => write!(f, "None"),
+            AttrValue::Bool(b) => write!(f, "{}", if *b { "True" } else { "False" }),
+            AttrValue::Int(i) => write!(f, "{}", i),
+            AttrValue::Float(x) => write!(f, "{:?}", x),
+            AttrValue::Str(s) => write!(f, "{:?}", s),
+            AttrValue::List(items) | AttrValue::Tuple(items) => {
+                let (open, close) = if matches!(self, AttrValue::List(_)) { ("[", "]") } else { ("(", ")") };
+                write!(f, "{}", open)?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "{}", close)
+            }
+            AttrValue::Dict(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            AttrValue::Object(obj) => write!(f, "{}", obj.0),
+        }
+    }
+}
+
+/// A Python object that has no Rust counterpart.
+#[derive(Debug)]
+pub struct PyValue(Py<PyAny>);
+
+impl Clone for PyValue {
+    fn clone(&self) -> Self {
+        Python::attach(|py| PyValue(self.0.clone_ref(py)))
+    }
+}
+
+impl PartialEq for PyValue {
+    fn eq(&self, other: &Self) -> bool {
+        Python::attach(|py| self.0.bind(py).eq(other.0.bind(py)).unwrap_or(false))
+    }
+}
+
+impl Hash for PyValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // 不可哈希的对象统一用 0，相等性仍由 __eq__ 决定
+        Python::attach(|py| (self.0.bind(py).hash().unwrap_or(0) as i64).hash(state));
+    }
+}
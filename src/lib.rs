@@ -1,3 +1,4 @@
+pub mod algorithm;
 pub mod graph;
 pub mod utils;
 
@@ -6,7 +7,8 @@ use pyo3::prelude::*;
 
 #[pymodule]
 fn simulation(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    // graph::register_graph_module(m)?;
+    m.add_class::<graph::networkx_graph::NetworkXGraph>()?;
+    m.add_class::<graph::networkx_graph::SimulationSession>()?;
     m.add_class::<graph::hypergraph::Hypergraph>()?;
     m.add_class::<graph::hypergraph::Node>()?;
     m.add_class::<graph::hypergraph::Hyperedge>()?;
@@ -15,12 +17,29 @@ fn simulation(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<graph::hypergraph::DMatchImpl>()?;
     m.add_class::<graph::hypergraph::Event>()?;
 
+    m.add("UnknownNodeError", m.py().get_type::<graph::error::UnknownNodeError>())?;
+    m.add("CompareFunctionError", m.py().get_type::<graph::error::CompareFunctionError>())?;
+
     m.add_function(wrap_pyfunction!(graph::networkx_graph::get_simulation_inter, m)?)?;
     m.add_function(wrap_pyfunction!(graph::networkx_graph::get_simulation_inter_fn, m)?)?;
     m.add_function(wrap_pyfunction!(graph::networkx_graph::is_simulation_isomorphic, m)?)?;
     m.add_function(wrap_pyfunction!(graph::networkx_graph::is_simulation_isomorphic_fn, m)?)?;
     m.add_function(wrap_pyfunction!(graph::networkx_graph::is_simulation_isomorphic_of_edge_fn, m)?)?;
     m.add_function(wrap_pyfunction!(graph::networkx_graph::is_simulation_isomorphic_of_node_edge_fn, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::get_simulation_of_edge_fn, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::get_simulation_of_node_edge_fn, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::get_fractional_simulation, m)?)?;
     m.add_function(wrap_pyfunction!(graph::networkx_graph::get_bounded_simulation, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::get_dual_simulation, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::is_dual_simulation_isomorphic, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::get_strong_simulation, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::get_top_k_simulation, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::explain_simulation, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::batch_simulation, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::simulation_matrix, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::bisimulation_classes, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::quotient_graph, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::minimize_query, m)?)?;
+    m.add_function(wrap_pyfunction!(graph::networkx_graph::query_contained, m)?)?;
     Ok(())
 }
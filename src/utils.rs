@@ -1,2 +0,0 @@
-use pyo3::prelude::*;
-
@@ -0,0 +1,1261 @@
+import re
+
+import networkx as nx
+import pytest
+import simulation
+
+
+def attr_same(attr1: dict, attr2: dict):
+    return attr1.get("label") == attr2.get("label")
+
+
+def test_undirected_edges_match_both_directions():
+    query = nx.Graph()
+    query.add_node(0, label="A")
+    query.add_node(1, label="B")
+    query.add_edge(0, 1)
+
+    data = nx.Graph()
+    data.add_node(0, label="B")
+    data.add_node(1, label="A")
+    data.add_edge(0, 1)
+
+    sim = simulation.get_simulation_inter_fn(query, data, attr_same)
+    assert sim == {0: {1}, 1: {0}}
+
+
+def test_directed_edges_keep_direction():
+    query = nx.DiGraph()
+    query.add_node(0, label="A")
+    query.add_node(1, label="B")
+    query.add_edge(0, 1)
+
+    data = nx.DiGraph()
+    data.add_node(0, label="B")
+    data.add_node(1, label="A")
+    data.add_edge(0, 1)
+
+    assert not simulation.is_simulation_isomorphic_fn(query, data, attr_same)
+
+
+def test_parallel_edges_are_matched_individually():
+    def edge_same(attr1: dict, attr2: dict):
+        return attr1.get("relation") == attr2.get("relation")
+
+    query = nx.MultiDiGraph()
+    query.add_node(0, label="Person")
+    query.add_node(1, label="City")
+    query.add_edge(0, 1, key="born", relation="born_in")
+    query.add_edge(0, 1, key="lives", relation="lives_in")
+
+    data = nx.MultiDiGraph()
+    data.add_node(0, label="Person")
+    data.add_node(1, label="City")
+    data.add_edge(0, 1, key="born", relation="born_in")
+
+    assert not simulation.is_simulation_isomorphic_of_node_edge_fn(query, data, attr_same, edge_same)
+
+    data.add_edge(0, 1, key="lives", relation="lives_in")
+    assert simulation.is_simulation_isomorphic_of_node_edge_fn(query, data, attr_same, edge_same)
+
+
+def test_results_use_original_node_keys():
+    query = nx.DiGraph()
+    query.add_node("person", label="A")
+    query.add_node(("city", 1), label="B")
+    query.add_edge("person", ("city", 1))
+
+    data = nx.DiGraph()
+    data.add_node("alice", label="A")
+    data.add_node(("paris", 75), label="B")
+    data.add_edge("alice", ("paris", 75))
+
+    expected = {"person": {"alice"}, ("city", 1): {("paris", 75)}}
+    assert simulation.get_simulation_inter_fn(query, data, attr_same) == expected
+    assert simulation.get_bounded_simulation(query, data, attr_same, lambda attrs: 1) == expected
+
+
+class SameStr:
+    def __init__(self, name):
+        self.name = name
+
+    def __str__(self):
+        return "node"
+
+
+def test_node_keys_of_different_types_stay_distinct():
+    query = nx.DiGraph()
+    query.add_node(1, label="A")
+    query.add_node("1", label="B")
+    query.add_edge(1, "1")
+
+    data = nx.DiGraph()
+    first, second = SameStr("first"), SameStr("second")
+    data.add_node(first, label="A")
+    data.add_node(second, label="B")
+    data.add_edge(first, second)
+
+    assert simulation.get_simulation_inter_fn(query, data, attr_same) == {1: {first}, "1": {second}}
+
+
+def test_nan_node_key_is_rejected():
+    graph = nx.DiGraph()
+    graph.add_node(float("nan"), label="A")
+
+    with pytest.raises(ValueError, match="ambiguous node key"):
+        simulation.get_simulation_inter_fn(graph, graph, attr_same)
+
+
+def test_label_keys_compare_natively():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A", type=1)
+    query.add_node("q1", label="B", type=2, note="ignored")
+    query.add_edge("q0", "q1")
+
+    data = nx.DiGraph()
+    data.add_node("d0", label="A", type=1.0)
+    data.add_node("d1", label="B", type=2)
+    data.add_node("d2", label="B", type=3)
+    data.add_edge("d0", "d1")
+    data.add_edge("d0", "d2")
+
+    expected = {"q0": {"d0"}, "q1": {"d1"}}
+    assert simulation.get_simulation_inter(query, data, label_keys=["label", "type"]) == expected
+    assert simulation.get_simulation_inter(query, data, is_label_cached=True, label_keys=["label", "type"]) == expected
+    assert simulation.get_simulation_inter(query, data, label_keys=["label"]) == {"q0": {"d0"}, "q1": {"d1", "d2"}}
+
+
+def test_label_key_fn_groups_candidates():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A", size=1)
+    query.add_node("q1", label="B", size=5)
+    query.add_edge("q0", "q1")
+
+    data = nx.DiGraph()
+    data.add_node("d0", label="A", size=2)
+    data.add_node("d1", label="B", size=9)
+    data.add_node("d2", label="B", size=3)
+    data.add_node("d3", label="C", size=9)
+    data.add_edge("d0", "d1")
+    data.add_edge("d0", "d2")
+    data.add_edge("d0", "d3")
+
+    calls = []
+
+    def bigger(a, b):
+        calls.append((a["label"], b["label"]))
+        return b["size"] >= a["size"]
+
+    expected = {"q0": {"d0"}, "q1": {"d1"}}
+    for cached in (False, True):
+        calls.clear()
+        result = simulation.get_simulation_inter_fn(query, data, bigger, is_label_cached=cached,
+                                                    label_key_fn=lambda attrs: attrs["label"])
+        assert result == expected
+        assert all(a == b for a, b in calls)
+
+    # Without grouping, bigger ignores the label
+    assert simulation.get_simulation_inter_fn(query, data, bigger) == {"q0": {"d0"}, "q1": {"d1", "d3"}}
+
+
+def test_label_keys_and_label_key_fn_are_exclusive():
+    graph = nx.DiGraph()
+    graph.add_node(0, label="A")
+    with pytest.raises(ValueError, match="cannot be used together"):
+        simulation.get_simulation_inter(graph, graph, label_keys=["label"], label_key_fn=lambda attrs: attrs["label"])
+
+
+def test_native_attributes_compare_by_value():
+    query = nx.DiGraph()
+    query.add_node(0, label="A", tags=["x", "y"])
+    query.add_node(1, label="B", meta={"weight": 1})
+    query.add_edge(0, 1)
+
+    data = nx.DiGraph()
+    data.add_node(0, label="A", tags=["x", "y"], extra=None)
+    data.add_node(1, label="B", meta={"weight": 1.0})
+    data.add_node(2, label="B", meta={"weight": 2})
+    data.add_edge(0, 1)
+    data.add_edge(0, 2)
+
+    assert simulation.get_simulation_inter(query, data) == {0: {0}, 1: {1}}
+
+
+def test_large_ints_and_floats_compare_exactly():
+    query = nx.DiGraph()
+    query.add_node("q0", weight=2**53 + 1)
+    query.add_node("q1", weight=2**53)
+
+    data = nx.DiGraph()
+    data.add_node("d0", weight=2.0**53)
+    data.add_node("d1", weight=2**53 + 1)
+    data.add_node("d2", weight=float("inf"))
+
+    # like Python, 2**53 + 1 != 2.0**53 == 2**53
+    expected = {"q0": {"d1"}, "q1": {"d0"}}
+    assert simulation.get_simulation_inter(query, data) == expected
+    assert simulation.get_simulation_inter(query, data, label_keys=["weight"]) == expected
+
+
+def test_numeric_objects_equal_native_numbers():
+    from enum import IntEnum
+    from fractions import Fraction
+
+    class Size(IntEnum):
+        SMALL = 3
+
+    query = nx.DiGraph()
+    query.add_node("q0", w=Size.SMALL)
+    query.add_node("q1", w=2**70)
+    query.add_node("q2", w=Fraction(1, 2))
+
+    data = nx.DiGraph()
+    data.add_node("d0", w=3)
+    data.add_node("d1", w=3.0)
+    data.add_node("d2", w=2.0**70)
+    data.add_node("d3", w=0.5)
+    data.add_node("d4", w=4)
+
+    expected = {"q0": {"d0", "d1"}, "q1": {"d2"}, "q2": {"d3"}}
+    assert simulation.get_simulation_inter(query, data) == expected
+    # equal labels must also hash alike to land in the same bucket
+    result = simulation.get_simulation_inter_fn(query, data, lambda a, b: True, is_label_cached=True, label_keys=["w"])
+    assert result == expected
+
+
+def test_callbacks_receive_python_values():
+    seen = []
+
+    def compare(attr1: dict, attr2: dict):
+        seen.append(attr2)
+        return attr1["label"] == attr2["label"]
+
+    graph = nx.DiGraph()
+    graph.add_node(0, label="A", pos=(1.5, 2), tags=["x"], flag=True, obj=frozenset({1}))
+
+    assert simulation.get_simulation_inter_fn(graph, graph, compare) == {0: {0}}
+    assert seen[0] == {"label": "A", "pos": (1.5, 2), "tags": ["x"], "flag": True, "obj": frozenset({1})}
+
+
+def random_labeled_graph(n, p, k, seed):
+    import random
+
+    rng = random.Random(seed)
+    graph = nx.fast_gnp_random_graph(n, p, seed=seed, directed=True)
+    for node in graph.nodes:
+        graph.nodes[node]["label"] = rng.randint(1, k)
+    return graph
+
+
+def test_label_cache_matches_uncached_results():
+    query = random_labeled_graph(30, 0.1, 3, seed=1)
+    data = random_labeled_graph(200, 0.05, 3, seed=2)
+
+    expected = simulation.get_simulation_inter_fn(query, data, attr_same)
+    assert simulation.get_simulation_inter_fn(query, data, attr_same, is_label_cached=True) == expected
+    assert simulation.get_simulation_inter(query, data, is_label_cached=True, label_keys=["label"]) == expected
+
+
+def reference_simulation(query, data):
+    sim = {u: {v for v in data if data.nodes[v] == query.nodes[u]} for u in query}
+    changed = True
+    while changed:
+        changed = False
+        for u, u_next in query.edges:
+            keep = {v for v in sim[u] if any(v_next in sim[u_next] for v_next in data.successors(v))}
+            if keep != sim[u]:
+                sim[u] = keep
+                changed = True
+    return sim
+
+
+def test_simulation_matches_reference_on_random_graphs():
+    for seed in range(5):
+        query = random_labeled_graph(8, 0.3, 2, seed=seed)
+        data = random_labeled_graph(60, 0.08, 2, seed=seed + 100)
+        assert simulation.get_simulation_inter(query, data) == reference_simulation(query, data)
+
+
+def test_bounded_simulation_sees_sinks():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A")
+    query.add_node("q1", label="B")
+    query.add_edge("q0", "q1")
+
+    data = nx.DiGraph()
+    data.add_node("a0", label="A")
+    data.add_node("a1", label="A")
+    data.add_node("mid", label="C")
+    data.add_node("b", label="B")
+    data.add_edges_from([("a0", "mid"), ("mid", "b")])
+
+    result = simulation.get_bounded_simulation(query, data, attr_same, lambda attrs: 2)
+    assert result == {"q0": {"a0"}, "q1": {"b"}}
+
+
+def test_bounded_simulation_default_and_invalid_bounds_are_zero():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A")
+    query.add_node("q1", label="B")
+    query.add_edge("q0", "q1")
+
+    data = nx.DiGraph()
+    data.add_node("a", label="A")
+    data.add_node("b", label="B")
+    data.add_edge("a", "b")
+
+    expected = {"q0": {"a"}, "q1": {"b"}}
+    assert simulation.get_bounded_simulation(query, data, attr_same) == expected
+
+    def failing(attrs):
+        raise RuntimeError("no bound")
+
+    # bounds that are not ints or "*" fall back to 0 instead of raising
+    for bound in (lambda attrs: 0, lambda attrs: -1, lambda attrs: "many", lambda attrs: 1.5, failing):
+        result, witnesses = simulation.get_bounded_simulation(query, data, attr_same, bound, return_witnesses=True)
+        assert result == expected
+        assert witnesses == {("q0", "q1"): {}}
+    result, witnesses = simulation.get_bounded_simulation(query, data, attr_same, edge_bound=-1, return_witnesses=True)
+    assert witnesses == {("q0", "q1"): {}}
+
+
+def bounded_chain():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A")
+    query.add_node("q1", label="B")
+    query.add_node("q2", label="C")
+    query.add_edge("q0", "q1", hops=3)
+    query.add_edge("q1", "q2")
+
+    data = nx.DiGraph()
+    data.add_node("a", label="A")
+    data.add_node("b", label="B")
+    data.add_node("c", label="C")
+    for node in ("x1", "x2", "y1"):
+        data.add_node(node, label="X")
+    nx.add_path(data, ["a", "x1", "x2", "b", "y1", "c"])
+    return query, data
+
+
+def test_bounded_simulation_per_edge_bounds():
+    query, data = bounded_chain()
+    expected = {"q0": {"a"}, "q1": {"b"}, "q2": {"c"}}
+
+    def bounded_edges(**kwargs):
+        result, witnesses = simulation.get_bounded_simulation(query, data, attr_same, return_witnesses=True, **kwargs)
+        assert result == expected
+        return {edge for edge, pairs in witnesses.items() if pairs}
+
+    # only q0 -> q1 carries hops, q1 -> q2 keeps the node bound of 0
+    assert bounded_edges(edge_bound="hops") == {("q0", "q1")}
+    assert bounded_edges(bound=lambda attrs: 2, edge_bound="hops") == {("q0", "q1"), ("q1", "q2")}
+    query.edges["q1", "q2"]["hops"] = 2
+    assert bounded_edges(edge_bound="hops") == {("q0", "q1"), ("q1", "q2")}
+
+    def by_label(src, edge, dst):
+        return "*" if src["label"] == "A" else 1
+
+    assert bounded_edges(edge_bound=by_label) == {("q0", "q1")}
+    assert bounded_edges(edge_bound="*") == {("q0", "q1"), ("q1", "q2")}
+    assert bounded_edges(edge_bound=2) == {("q1", "q2")}
+
+    # edge bounds override node bounds
+    assert bounded_edges(bound=lambda attrs: 1, edge_bound="*") == {("q0", "q1"), ("q1", "q2")}
+
+
+def test_bounded_simulation_witnesses():
+    query, data = bounded_chain()
+    result, witnesses = simulation.get_bounded_simulation(query, data, attr_same, edge_bound="*", return_witnesses=True)
+    assert result == {"q0": {"a"}, "q1": {"b"}, "q2": {"c"}}
+    assert witnesses == {
+        ("q0", "q1"): {("a", "b"): ["a", "x1", "x2", "b"]},
+        ("q1", "q2"): {("b", "c"): ["b", "y1", "c"]},
+    }
+
+
+def test_bounded_simulation_witnesses_are_shortest_paths():
+    for seed in range(10):
+        query = random_labeled_graph(4, 0.4, 2, seed=seed)
+        data = random_labeled_graph(25, 0.1, 2, seed=seed + 50)
+        dist = dict(nx.all_pairs_shortest_path_length(data))
+        result, witnesses = simulation.get_bounded_simulation(query, data, attr_same, lambda attrs: 3, return_witnesses=True)
+        if not result:
+            assert witnesses == {}
+            continue
+        assert set(witnesses) == set(query.edges)
+        for (u, u2), pairs in witnesses.items():
+            for (v, w), path in pairs.items():
+                assert v in result[u] and w in result[u2]
+                assert path[0] == v and path[-1] == w and len(path) - 1 == dist[v][w] <= 3
+                assert all(data.has_edge(x, y) for x, y in zip(path, path[1:]))
+            # every reachable matched pair has a witness
+            for v in result[u]:
+                for w in result[u2]:
+                    if dist[v].get(w, 4) <= 3:
+                        assert (v, w) in pairs
+
+
+def small_pair():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A")
+    query.add_node("q1", label="B")
+    query.add_edge("q0", "q1")
+
+    data = nx.DiGraph()
+    data.add_node("d0", label="A")
+    data.add_node("d1", label="B")
+    data.add_edge("d0", "d1")
+    return query, data
+
+
+def test_compare_errors_name_the_node_pair():
+    query, data = small_pair()
+
+    def broken(a, b):
+        return a["missing"] == b["missing"]
+
+    for cached in (False, True):
+        with pytest.raises(simulation.CompareFunctionError, match="'q0' and 'd0'") as info:
+            simulation.get_simulation_inter_fn(query, data, broken, is_label_cached=cached)
+        assert isinstance(info.value.__cause__, KeyError)
+        assert info.value.__cause__.__traceback__ is not None
+
+
+def test_compare_function_must_return_bool():
+    query, data = small_pair()
+    with pytest.raises(simulation.CompareFunctionError) as info:
+        simulation.is_simulation_isomorphic_fn(query, data, lambda a, b: "yes")
+    assert isinstance(info.value.__cause__, TypeError)
+    assert "str" in str(info.value.__cause__)
+
+
+def test_edge_compare_errors_name_the_edges():
+    query, data = small_pair()
+
+    def broken(a, b):
+        raise RuntimeError("boom")
+
+    with pytest.raises(simulation.CompareFunctionError, match=re.escape("('q0', 'q1') and ('d0', 'd1')")) as info:
+        simulation.is_simulation_isomorphic_of_node_edge_fn(query, data, attr_same, broken)
+    assert isinstance(info.value.__cause__, RuntimeError)
+
+
+def test_graph_class_can_be_built_and_reused():
+    query = simulation.Graph()
+    query.add_node("q0", label="A")
+    query.add_node("q1", label="B")
+    query.add_edge("q0", "q1")
+
+    data = simulation.Graph.from_networkx(nx.DiGraph([("d0", "d1"), ("d1", "d2")]))
+    data.add_node("d0", label="A")
+    data.add_node("d1", label="B")
+    data.add_node("d2", label="B")
+    assert len(data) == 3 and data.edge_count() == 2
+
+    calls = []
+
+    def counting_same(a, b):
+        calls.append(1)
+        return attr_same(a, b)
+
+    expected = {"q0": {"d0"}, "q1": {"d1", "d2"}}
+    assert simulation.get_simulation_inter_fn(query, data, counting_same, is_label_cached=True) == expected
+    first = len(calls)
+    assert simulation.get_simulation_inter_fn(query, data, counting_same, is_label_cached=True) == expected
+    assert len(calls) == first
+
+    # Changing the data graph invalidates the cache kept on the query
+    data.add_node("d3", label="A")
+    data.add_edge("d3", "d2")
+    assert simulation.get_simulation_inter_fn(query, data, counting_same, is_label_cached=True) == {
+        "q0": {"d0", "d3"}, "q1": {"d1", "d2"},
+    }
+    assert len(calls) > first
+
+
+def test_per_call_options_do_not_stick_to_graphs():
+    query = simulation.Graph()
+    query.add_node("q0", label="A", type=1)
+    data = simulation.Graph()
+    data.add_node("d0", label="A", type=1)
+    data.add_node("d1", label="A", type=2)
+
+    by_label = {"q0": {"d0", "d1"}}
+    by_value = {"q0": {"d0"}}
+    for cached in (False, True):
+        assert simulation.get_simulation_inter(query, data, cached, label_keys=["label"]) == by_label
+        assert simulation.get_simulation_inter(query, data, cached) == by_value
+        assert simulation.get_simulation_inter_fn(query, data, lambda a, b: True, cached) == by_label
+        assert simulation.get_simulation_inter(query, data, cached) == by_value
+
+    # settings registered on the graphs are kept, and a call can still override them
+    query.set_label_keys(["label"])
+    data.set_label_keys(["label"])
+    assert simulation.get_simulation_inter(query, data) == by_label
+    assert simulation.get_simulation_inter(query, data, label_keys=["label", "type"]) == by_value
+    assert simulation.get_simulation_inter(query, data, True) == by_label
+    query.register_compare_fn(lambda a, b: a["type"] == b["type"])
+    assert simulation.get_simulation_inter_fn(query, data, lambda a, b: True) == by_label
+    assert simulation.get_simulation_inter(query, data) == by_value
+
+
+def test_label_cache_is_only_used_for_its_graph():
+    query = simulation.Graph()
+    query.add_node("x", label="A")
+    query.add_node("y", label="B")
+    query.register_compare_fn(attr_same)
+
+    data = simulation.Graph()
+    data.add_node("d0", label="B")
+    data.add_node("d1", label="A")
+    assert query.build_compare_cache(data) == 2
+
+    # the pairs cached against data must not be read when the query is compared with itself
+    assert simulation.bisimulation_classes(query) == {"x": 0, "y": 1}
+    assert len(simulation.minimize_query(query)) == 2
+    assert simulation.get_top_k_simulation(query, query, 1) == {"x": [("x", 1.0)], "y": [("y", 1.0)]}
+
+
+def test_graph_class_mixes_with_networkx_and_round_trips():
+    graph = simulation.Graph(directed=False)
+    graph.add_node(0, label="A")
+    graph.add_node(1, label="A")
+    graph.add_edge(0, 1, weight=1)
+    graph.add_edge(1, 0, weight=2)
+    assert graph.edge_count() == 1
+    assert graph.get_edge_attributes(0, 1) == {"weight": 2}
+
+    nx_graph = graph.to_networkx()
+    assert isinstance(nx_graph, nx.Graph) and not nx_graph.is_directed()
+    assert simulation.is_simulation_isomorphic(graph, nx_graph)
+    assert simulation.get_simulation_inter(graph, graph) == {0: {0, 1}, 1: {0, 1}}
+
+
+def test_unknown_edge_endpoint_raises():
+    graph = simulation.Graph()
+    graph.add_node("a")
+    assert issubclass(simulation.UnknownNodeError, KeyError)
+    with pytest.raises(simulation.UnknownNodeError, match="'b'"):
+        graph.add_edge("a", "b")
+
+
+def test_edge_aware_simulations_return_relations():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A")
+    query.add_node("q1", label="B")
+    query.add_edge("q0", "q1", label="knows")
+
+    data = nx.DiGraph()
+    data.add_node("d0", label="A")
+    data.add_node("d1", label="A")
+    data.add_node("d2", label="B")
+    data.add_edge("d0", "d2", label="knows")
+    data.add_edge("d1", "d2", label="likes")
+
+    result = simulation.get_simulation_of_node_edge_fn(query, data, attr_same, attr_same)
+    assert result == {"q0": {"d0"}, "q1": {"d2"}}
+    assert simulation.is_simulation_isomorphic_of_node_edge_fn(query, data, attr_same, attr_same)
+
+    def whole_edge_same(src1, edge1, dst1, src2, edge2, dst2):
+        return attr_same(src1, src2) and attr_same(edge1, edge2) and attr_same(dst1, dst2)
+
+    result = simulation.get_simulation_of_edge_fn(query, data, whole_edge_same)
+    assert result["q0"] == {"d0"}
+    assert result["q1"] == {"d0", "d1", "d2"}
+
+
+def test_edge_fn_accepts_label_keys():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A", age=1)
+    query.add_node("q1", label="B", age=2)
+    query.add_edge("q0", "q1", label="knows")
+
+    data = nx.DiGraph()
+    data.add_node("d0", label="A", age=3)
+    data.add_node("d1", label="C", age=4)
+    data.add_node("d2", label="B", age=5)
+    data.add_edge("d0", "d2", label="knows")
+    data.add_edge("d1", "d2", label="knows")
+
+    calls = []
+
+    def edge_same(src1, edge1, dst1, src2, edge2, dst2):
+        calls.append(src2["label"])
+        return edge1 == edge2
+
+    # endpoints with different labels are rejected without calling edge_same
+    result = simulation.get_simulation_of_edge_fn(query, data, edge_same, label_keys=["label"])
+    assert result["q0"] == {"d0"}
+    assert "C" not in calls
+    assert simulation.is_simulation_isomorphic_of_edge_fn(query, data, edge_same, label_key_fn=lambda attrs: attrs["label"])
+
+    # without labels edge_same alone decides, so d1 matches as well
+    assert simulation.get_simulation_of_edge_fn(query, data, edge_same)["q0"] == {"d0", "d1"}
+    with pytest.raises(ValueError):
+        simulation.get_simulation_of_edge_fn(query, data, edge_same, label_keys=["label"], label_key_fn=lambda attrs: attrs["label"])
+
+
+def reference_dual_simulation(query, data):
+    sim = reference_simulation(query, data)
+    changed = True
+    while changed:
+        changed = False
+        for u, u_next in query.edges:
+            keep = {v for v in sim[u] if any(w in sim[u_next] for w in data.successors(v))}
+            keep_next = {w for w in sim[u_next] if any(v in sim[u] for v in data.predecessors(w))}
+            if keep != sim[u] or keep_next != sim[u_next]:
+                sim[u], sim[u_next] = keep, keep_next
+                changed = True
+    return sim
+
+
+def test_dual_simulation_keeps_parents():
+    query = nx.DiGraph()
+    query.add_node("q0", label="A")
+    query.add_node("q1", label="B")
+    query.add_edge("q0", "q1")
+
+    data = nx.DiGraph()
+    data.add_node("d0", label="A")
+    data.add_node("d1", label="B")
+    data.add_node("orphan", label="B")
+    data.add_edge("d0", "d1")
+
+    assert simulation.get_simulation_inter(query, data) == {"q0": {"d0"}, "q1": {"d1", "orphan"}}
+    assert simulation.get_dual_simulation(query, data) == {"q0": {"d0"}, "q1": {"d1"}}
+    assert simulation.get_dual_simulation(query, data, attr_same, is_label_cached=True) == {"q0": {"d0"}, "q1": {"d1"}}
+    assert simulation.is_dual_simulation_isomorphic(query, data)
+
+    data.remove_edge("d0", "d1")
+    data.add_edge("d1", "d0")
+    assert not simulation.is_dual_simulation_isomorphic(query, data, attr_same)
+
+
+def test_dual_simulation_matches_reference_on_random_graphs():
+    for seed in range(5):
+        query = random_labeled_graph(5, 0.3, 2, seed=seed)
+        data = random_labeled_graph(60, 0.1, 2, seed=seed + 100)
+        assert simulation.get_dual_simulation(query, data) == reference_dual_simulation(query, data)
+
+
+def test_strong_simulation_matches_real_occurrences_only():
+    query = nx.DiGraph()
+    query.add_node("qa", label="A")
+    query.add_node("qb", label="B")
+    query.add_edges_from([("qa", "qb"), ("qb", "qa")])
+
+    data = nx.DiGraph()
+    # A six-cycle A->B->A->B->A->B is matched by dual simulation but holds no 2-cycle
+    ring = [f"r{i}" for i in range(6)]
+    for i, node in enumerate(ring):
+        data.add_node(node, label="AB"[i % 2])
+        data.add_edge(node, ring[(i + 1) % 6])
+    data.add_node("a", label="A")
+    data.add_node("b", label="B")
+    data.add_edges_from([("a", "b"), ("b", "a")])
+
+    assert simulation.get_dual_simulation(query, data)["qa"] == {"r0", "r2", "r4", "a"}
+
+    matches = simulation.get_strong_simulation(query, data)
+    assert sorted(match["center"] for match in matches) == ["a", "b"]
+    for match in matches:
+        assert match["relation"] == {"qa": {"a"}, "qb": {"b"}}
+        assert match["nodes"] == {"a", "b"}
+        assert sorted(match["edges"]) == [("a", "b"), ("b", "a")]
+
+
+def test_strong_simulation_on_undirected_graphs():
+    query = nx.Graph()
+    query.add_node(0, label="A")
+    query.add_node(1, label="B")
+    query.add_edge(0, 1)
+
+    data = nx.Graph()
+    data.add_node("x", label="A")
+    data.add_node("y", label="B")
+    data.add_node("z", label="C")
+    data.add_edges_from([("x", "y"), ("y", "z")])
+
+    matches = simulation.get_strong_simulation(query, data, attr_same)
+    assert [match["center"] for match in matches] == ["x", "y"]
+    assert all(match["edges"] == [("x", "y")] for match in matches)
+
+
+def test_strong_simulation_rejects_disconnected_queries():
+    query = nx.DiGraph()
+    query.add_node(0, label="A")
+    query.add_node(1, label="B")
+    with pytest.raises(ValueError, match="connected"):
+        simulation.get_strong_simulation(query, query)
+
+
+def test_graph_remove_edge_and_node():
+    graph = simulation.Graph(directed=False)
+    for node in "abc":
+        graph.add_node(node, label=node)
+    graph.add_edge("a", "b")
+    graph.add_edge("b", "c")
+    graph.remove_edge("b", "a")
+    assert graph.edge_count() == 1 and graph.get_edge_attributes("a", "b") is None
+
+    graph.remove_node("b")
+    assert len(graph) == 2 and graph.edge_count() == 0
+    assert graph.get_node_attributes("c") == {"label": "c"}
+    graph.add_edge("a", "c")
+    assert sorted(graph.to_networkx().edges) == [("a", "c")]
+
+    for remove in (lambda: graph.remove_edge("c", "x"), lambda: graph.remove_node("b")):
+        with pytest.raises(simulation.UnknownNodeError):
+            remove()
+    with pytest.raises(KeyError, match="no edge") as info:
+        graph.remove_edge("a", "a")
+    assert not isinstance(info.value, simulation.UnknownNodeError)
+
+
+def reference_relation_pairs(query, data, same):
+    sim = {u: {v for v in data if same(query.nodes[u], data.nodes[v])} for u in query}
+    # neighbors() covers both directions of an undirected edge
+    arcs = [(u, u_next) for u in query for u_next in query.neighbors(u)]
+    changed = True
+    while changed:
+        changed = False
+        for u, u_next in arcs:
+            keep = {v for v in sim[u] if any(w in sim[u_next] for w in data.neighbors(v))}
+            if keep != sim[u]:
+                sim[u] = keep
+                changed = True
+    return {(u, v) for u in sim for v in sim[u]}
+
+
+def test_simulation_session_tracks_updates():
+    import random
+
+    for directed in (True, False):
+        rng = random.Random(7)
+        query = random_labeled_graph(5, 0.4, 2, seed=3)
+        data = random_labeled_graph(30, 0.08, 2, seed=4)
+        if not directed:
+            query, data = query.to_undirected(), data.to_undirected()
+        for compare, cached in ((None, False), (attr_same, False), (attr_same, True)):
+            same = compare or (lambda a, b: a == b)
+            session = simulation.SimulationSession(query, data, compare, is_label_cached=cached)
+            current = data.copy()
+            pairs = reference_relation_pairs(query, current, same)
+            assert {(u, v) for u, vs in session.relation().items() for v in vs} == pairs
+            next_node = 100
+            for _ in range(60):
+                action = rng.random()
+                if action < 0.35:
+                    source, target = rng.sample(sorted(current.nodes), 2)
+                    changes = session.insert_edge(source, target)
+                    current.add_edge(source, target)
+                elif action < 0.7 and current.number_of_edges():
+                    source, target = rng.choice(sorted(current.edges))
+                    changes = session.delete_edge(source, target)
+                    current.remove_edge(source, target)
+                elif action < 0.85:
+                    # relabel an existing node or add a fresh one
+                    node = rng.choice([next_node, rng.choice(sorted(current.nodes))])
+                    next_node += 1
+                    label = rng.randint(1, 2)
+                    changes = session.insert_node(node, label=label)
+                    current.add_node(node, label=label)
+                else:
+                    node = rng.choice(sorted(current.nodes))
+                    changes = session.delete_node(node)
+                    current.remove_node(node)
+                expected = reference_relation_pairs(query, current, same)
+                assert changes == (expected - pairs, pairs - expected)
+                assert {(u, v) for u, vs in session.relation().items() for v in vs} == expected
+                pairs = expected
+
+
+def test_simulation_session_keeps_data_on_compare_errors():
+    query, data = small_pair()
+    calls = []
+
+    def flaky(a, b):
+        calls.append(1)
+        if b.get("broken"):
+            raise RuntimeError("boom")
+        return a["label"] == b["label"]
+
+    session = simulation.SimulationSession(query, data, flaky)
+    with pytest.raises(simulation.CompareFunctionError) as info:
+        session.insert_node("d2", label="A", broken=True)
+    assert isinstance(info.value.__cause__, RuntimeError)
+    assert session.insert_node("d2", label="A") == (set(), set())
+    assert session.insert_edge("d2", "d1") == ({("q0", "d2")}, set())
+    assert session.relation() == {"q0": {"d0", "d2"}, "q1": {"d1"}}
+
+
+def reference_top_k(query, data, k, score):
+    sim = reference_simulation(query, data)
+    ranked = {}
+    for u, matches in sim.items():
+        order = sorted(matches, key=lambda v: (-score(u, v), v))
+        ranked[u] = [(v, score(u, v)) for v in order[:k]]
+    return ranked
+
+
+def test_top_k_simulation_matches_reference():
+    def degree(u, v):
+        du, dv = query.degree(u), data.degree(v)
+        return min(du, dv) / max(du, dv) if max(du, dv) else 1.0
+
+    def neighborhood(u, v):
+        neighbours = {}
+        for w in data.successors(v):
+            neighbours[w] = neighbours.get(w, False) or any(data.nodes[w] == query.nodes[x] for x in query.successors(u))
+        for w in data.predecessors(v):
+            neighbours[w] = neighbours.get(w, False) or any(data.nodes[w] == query.nodes[x] for x in query.predecessors(u))
+        return sum(neighbours.values()) / len(neighbours) if neighbours else 1.0
+
+    for seed in range(5):
+        query = random_labeled_graph(6, 0.3, 2, seed=seed)
+        data = random_labeled_graph(60, 0.08, 2, seed=seed + 100)
+        for k in (1, 3, 100):
+            assert simulation.get_top_k_simulation(query, data, k, "degree") == reference_top_k(query, data, k, degree)
+            expected = reference_top_k(query, data, k, neighborhood)
+            result = simulation.get_top_k_simulation(query, data, k)
+            assert {u: [v for v, _ in matches] for u, matches in result.items()} == {u: [v for v, _ in matches] for u, matches in expected.items()}
+            assert all(abs(a[1] - b[1]) < 1e-9 for u in result for a, b in zip(result[u], expected[u]))
+
+
+def test_top_k_simulation_with_score_function():
+    query, data = small_pair()
+    data.add_node("d2", label="A", weight=5)
+    data.add_node("d3", label="A", weight=9)
+    data.add_edge("d2", "d1")
+
+    def by_weight(query_attrs, data_attrs):
+        return data_attrs.get("weight", 0)
+
+    result = simulation.get_top_k_simulation(query, data, 1, by_weight, attr_same)
+    # d3 has the highest score but no B successor, so it is never confirmed
+    assert result == {"q0": [("d2", 5.0)], "q1": [("d1", 0.0)]}
+
+    with pytest.raises(ValueError, match="closeness"):
+        simulation.get_top_k_simulation(query, data, 1, "closeness")
+
+
+def test_top_k_simulation_scores_lazily():
+    query = nx.DiGraph()
+    for node, label in (("q0", "A"), ("q1", "B"), ("q2", "C")):
+        query.add_node(node, label=label, name=node)
+    query.add_edges_from([("q0", "q1"), ("q1", "q2")])
+
+    data = nx.DiGraph()
+    for node, label in (("a", "A"), ("b1", "B"), ("b2", "B"), ("c", "C")):
+        data.add_node(node, label=label, name=node)
+    data.add_edges_from([("a", "b1"), ("a", "b2"), ("b1", "c")])
+
+    scored = []
+
+    def record(query_attrs, data_attrs):
+        scored.append((query_attrs["name"], data_attrs["name"]))
+        return 1.0
+
+    assert simulation.get_top_k_simulation(query, data, 0, record, attr_same) == {"q0": [], "q1": [], "q2": []}
+    assert scored == []
+
+    result = simulation.get_top_k_simulation(query, data, 1, record, attr_same)
+    assert result == {"q0": [("a", 1.0)], "q1": [("b1", 1.0)], "q2": [("c", 1.0)]}
+    # confirming a rules out b2 for q1 before q1 is scored
+    assert sorted(scored) == [("q0", "a"), ("q1", "b1"), ("q2", "c")]
+
+
+def test_top_k_simulation_score_function_errors():
+    query, data = small_pair()
+
+    def failing(query_attrs, data_attrs):
+        raise RuntimeError("no score")
+
+    with pytest.raises(simulation.CompareFunctionError, match="score function failed on nodes 'q0' and 'd0'") as info:
+        simulation.get_top_k_simulation(query, data, 1, failing, attr_same)
+    assert isinstance(info.value.__cause__, RuntimeError)
+
+    with pytest.raises(simulation.CompareFunctionError, match="score function failed"):
+        simulation.get_top_k_simulation(query, data, 1, lambda query_attrs, data_attrs: "high", attr_same)
+
+
+def test_explain_simulation_follows_the_removal_chain():
+    query = nx.DiGraph()
+    for node, label in (("q0", "A"), ("q1", "B"), ("q2", "C")):
+        query.add_node(node, label=label)
+    query.add_edges_from([("q0", "q1"), ("q1", "q2")])
+
+    data = nx.DiGraph()
+    for node, label in (("a", "A"), ("b", "B"), ("x", "X")):
+        data.add_node(node, label=label)
+    data.add_edges_from([("a", "b"), ("b", "x")])
+
+    chain = simulation.explain_simulation(query, data, "q0", "a", attr_same)
+    assert chain == [
+        {"pair": ("q0", "a"), "reason": "successor", "edge": ("q0", "q1"), "removed": ("q1", "b")},
+        {"pair": ("q1", "b"), "reason": "successor", "edge": ("q1", "q2"), "removed": None},
+    ]
+    assert simulation.explain_simulation(query, data, "q2", "x", attr_same) == [{"pair": ("q2", "x"), "reason": "label"}]
+    assert simulation.explain_simulation(query, data, "q0", compare=attr_same) == {"a": chain}
+    assert simulation.explain_simulation(query, data, "q2", compare=attr_same) == {}
+
+    data.add_node("c", label="C")
+    data.add_edge("b", "c")
+    assert simulation.explain_simulation(query, data, "q0", "a", attr_same) == []
+
+
+def test_explain_simulation_chains_are_consistent():
+    for seed in range(5):
+        query = random_labeled_graph(6, 0.3, 2, seed=seed)
+        data = random_labeled_graph(40, 0.06, 2, seed=seed + 100)
+        sim = reference_simulation(query, data)
+        for u in query:
+            explained = simulation.explain_simulation(query, data, u)
+            candidates = {v for v in data if data.nodes[v] == query.nodes[u]}
+            assert set(explained) == candidates - sim[u]
+            for v, chain in explained.items():
+                assert chain[0]["pair"] == (u, v)
+                for step, following in zip(chain, chain[1:]):
+                    assert step["removed"] == following["pair"]
+                for step in chain:
+                    a, b = step["pair"]
+                    assert b not in sim[a] and step["edge"][0] == a
+                    assert step["edge"] in query.edges
+                last = chain[-1]
+                a_next = last["edge"][1]
+                assert last["removed"] is None
+                assert not any(data.nodes[w] == query.nodes[a_next] for w in data.successors(last["pair"][1]))
+
+
+def test_fractional_simulation_with_crisp_scores_is_simulation():
+    for seed in range(5):
+        query = random_labeled_graph(6, 0.3, 2, seed=seed)
+        data = random_labeled_graph(50, 0.08, 2, seed=seed + 100)
+        result = simulation.get_fractional_simulation(query, data, attr_same, threshold=1.0)
+        assert {u: set(scores) for u, scores in result.items()} == reference_simulation(query, data)
+        assert all(score == 1.0 for scores in result.values() for score in scores.values())
+
+
+def test_fractional_simulation_propagates_scores():
+    query = nx.DiGraph()
+    for node, label in (("q0", "A"), ("q1", "B"), ("q2", "C")):
+        query.add_node(node, label=label)
+    query.add_edges_from([("q0", "q1"), ("q0", "q2")])
+
+    data = nx.DiGraph()
+    for node, label in (("a", "A"), ("b", "b"), ("c", "C")):
+        data.add_node(node, label=label)
+    data.add_edge("a", "b")
+
+    def fuzzy(a, b):
+        if a["label"] == b["label"]:
+            return 1.0
+        return 0.8 if a["label"].lower() == b["label"].lower() else 0.0
+
+    result = simulation.get_fractional_simulation(query, data, fuzzy, threshold=0.0)
+    # q2 has no candidate below a, so a only gets half of the successor credit
+    assert result["q1"] == {"b": 0.8}
+    assert result["q2"] == {"c": 1.0}
+    assert abs(result["q0"]["a"] - 0.4) < 1e-9
+    assert simulation.get_fractional_simulation(query, data, fuzzy, threshold=0.5)["q0"] == {}
+
+
+def test_fractional_simulation_rejects_bad_scores():
+    query, data = small_pair()
+    for score, cause in ((1.5, ValueError), ("high", TypeError)):
+        with pytest.raises(simulation.CompareFunctionError, match="'q0' and 'd0'") as info:
+            simulation.get_fractional_simulation(query, data, lambda a, b: score)
+        assert isinstance(info.value.__cause__, cause)
+
+
+def test_batch_simulation_matches_single_calls():
+    query = random_labeled_graph(5, 0.3, 2, seed=2)
+    graphs = [random_labeled_graph(20, 0.1, 2, seed=seed) for seed in range(20)]
+    graphs[3] = simulation.Graph.from_networkx(graphs[3])
+
+    results = simulation.batch_simulation(query, graphs)
+    assert results == [simulation.get_simulation_inter(query, graph) for graph in graphs]
+    flags = simulation.batch_simulation(query, graphs, attr_same, isomorphic=True)
+    assert flags == [simulation.is_simulation_isomorphic_fn(query, graph, attr_same) for graph in graphs]
+    assert True in flags and False in flags
+    assert simulation.batch_simulation(query, graphs, label_keys=["label"]) == results
+
+
+def test_batch_simulation_reports_errors_per_graph():
+    query, data = small_pair()
+    broken = data.copy()
+    broken.nodes["d1"]["label"] = None
+
+    def strict(a, b):
+        return a["label"].lower() == b["label"].lower()
+
+    results = simulation.batch_simulation(query, [data, 42, broken, data], strict)
+    assert results[0] == results[3] == {"q0": {"d0"}, "q1": {"d1"}}
+    assert isinstance(results[1], Exception)
+    assert isinstance(results[2], simulation.CompareFunctionError)
+    assert "'d1'" in str(results[2]) and isinstance(results[2].__cause__, AttributeError)
+
+
+def test_batch_simulation_accepts_the_query_as_data():
+    query, data = (simulation.Graph.from_networkx(graph) for graph in small_pair())
+    results = simulation.batch_simulation(query, [query, data, query])
+    assert results == [{"q0": {"q0"}, "q1": {"q1"}}, {"q0": {"d0"}, "q1": {"d1"}}, {"q0": {"q0"}, "q1": {"q1"}}]
+    assert simulation.batch_simulation(query, [query], attr_same, isomorphic=True) == [True]
+
+
+def test_batch_simulation_keeps_the_query_cache():
+    query, data = (simulation.Graph.from_networkx(graph) for graph in small_pair())
+    calls = []
+
+    def counting_same(a, b):
+        calls.append(1)
+        return attr_same(a, b)
+
+    query.register_compare_fn(counting_same)
+    query.build_compare_cache(data)
+    simulation.batch_simulation(query, [data, data])
+    simulation.batch_simulation(query, [data], counting_same)
+
+    calls.clear()
+    assert simulation.get_simulation_inter(query, data, is_label_cached=True) == {"q0": {"d0"}, "q1": {"d1"}}
+    assert calls == []
+
+
+def test_simulation_matrix_matches_pairwise_calls():
+    graphs = [random_labeled_graph(n, 0.3, 2, seed=seed) for seed, n in enumerate([3, 4, 5, 6, 8, 10])]
+    graphs[2] = simulation.Graph.from_networkx(graphs[2])
+    expected = [[simulation.is_simulation_isomorphic(g1, g2) for g2 in graphs] for g1 in graphs]
+    assert simulation.simulation_matrix(graphs) == expected
+    assert any(not flag for row in expected for flag in row)
+
+    calls = []
+
+    def counting_same(a, b):
+        calls.append(1)
+        return attr_same(a, b)
+
+    assert simulation.simulation_matrix(graphs, counting_same) == expected
+    # only two distinct attribute dicts exist, so the table needs at most 2 x 2 calls
+    assert len(calls) <= 4
+    assert simulation.simulation_matrix(graphs, label_keys=["label"]) == expected
+
+    scores = simulation.simulation_matrix(graphs, score=True)
+    for i, g1 in enumerate(graphs):
+        for j, g2 in enumerate(graphs):
+            relation = simulation.get_simulation_inter(g1, g2)
+            share = sum(1 for matches in relation.values() if matches) / len(relation) if relation else 1.0
+            assert scores[i][j] == share
+
+
+def test_simulation_matrix_compares_only_undecided_classes():
+    query, data = (simulation.Graph.from_networkx(graph) for graph in small_pair())
+    calls = []
+
+    def counting_same(a, b):
+        calls.append((a["label"], b["label"]))
+        return attr_same(a, b)
+
+    query.register_compare_fn(counting_same)
+    query.build_compare_cache(data)
+    calls.clear()
+
+    # native labels already tell A from B, so compare only sees equal labels
+    matrix = simulation.simulation_matrix([query, data], counting_same, label_keys=["label"])
+    assert matrix == [[True, True], [True, True]]
+    assert sorted(calls) == [("A", "A"), ("B", "B")]
+
+    # the Graphs keep their compare function and label cache
+    calls.clear()
+    assert simulation.get_simulation_inter(query, data, is_label_cached=True) == {"q0": {"d0"}, "q1": {"d1"}}
+    assert calls == []
+
+
+def test_empty_data_graph_has_no_matches():
+    query, _ = small_pair()
+    for empty in (nx.DiGraph(), simulation.Graph()):
+        unmatched = {"q0": set(), "q1": set()}
+        assert simulation.get_simulation_inter(query, empty) == unmatched
+        assert simulation.get_simulation_inter_fn(query, empty, attr_same) == unmatched
+        assert simulation.get_dual_simulation(query, empty) == unmatched
+        assert not simulation.is_simulation_isomorphic(query, empty)
+        assert not simulation.is_simulation_isomorphic_fn(query, empty, attr_same)
+        assert not simulation.is_dual_simulation_isomorphic(query, empty)
+        assert simulation.batch_simulation(query, [empty]) == [unmatched]
+        assert simulation.batch_simulation(query, [empty], isomorphic=True) == [False]
+
+        assert simulation.get_simulation_inter(empty, empty) == {}
+        assert simulation.is_simulation_isomorphic(empty, empty)
+
+
+def test_simulation_matrix_uses_registered_compare():
+    query, data = small_pair()
+    for node in data:
+        data.nodes[node]["label"] = data.nodes[node]["label"].lower()
+    query = simulation.Graph.from_networkx(query)
+    query.register_compare_fn(lambda a, b: a["label"].lower() == b["label"].lower())
+
+    # the query Graph keeps its compare, data falls back to value-based matching
+    assert simulation.simulation_matrix([query, data]) == [[True, True], [False, True]]
+    assert simulation.simulation_matrix([query, data], attr_same) == [[True, False], [False, True]]
+
+
+def test_simulation_matrix_with_empty_graphs():
+    graph = random_labeled_graph(3, 0.3, 2, seed=0)
+    assert simulation.simulation_matrix([nx.DiGraph(), graph]) == [[True, True], [False, True]]
+    assert simulation.simulation_matrix([nx.DiGraph(), graph], score=True) == [[1.0, 1.0], [0.0, 1.0]]
+    assert simulation.simulation_matrix([]) == []
+
+
+def test_simulation_matrix_raises_compare_errors():
+    query, data = small_pair()
+    with pytest.raises(simulation.CompareFunctionError) as info:
+        simulation.simulation_matrix([query, data], lambda a, b: a["missing"])
+    assert isinstance(info.value.__cause__, KeyError)
+
+
+def test_bisimulation_classes_and_quotient():
+    graph = nx.DiGraph()
+    for node, label in (("a", "A"), ("b1", "B"), ("b2", "B"), ("c1", "C"), ("c2", "C"), ("b3", "B")):
+        graph.add_node(node, label=label)
+    graph.add_edges_from([("a", "b1"), ("a", "b2"), ("b1", "c1"), ("b2", "c2"), ("a", "b3")])
+
+    classes = simulation.bisimulation_classes(graph)
+    assert classes == {"a": 0, "b1": 1, "b2": 1, "c1": 2, "c2": 2, "b3": 3}
+
+    quotient = simulation.quotient_graph(graph)
+    assert isinstance(quotient, simulation.Graph) and quotient.is_directed()
+    compressed = quotient.to_networkx()
+    assert dict(compressed.nodes(data=True)) == {"a": {"label": "A"}, "b1": {"label": "B"}, "c1": {"label": "C"}, "b3": {"label": "B"}}
+    assert sorted(compressed.edges) == [("a", "b1"), ("a", "b3"), ("b1", "c1")]
+
+    # with only the first letter of the label compared, every C node and then every B node merge
+    def first_letter(attrs):
+        return attrs["label"][0]
+
+    graph.nodes["c2"]["label"] = "Cx"
+    assert simulation.bisimulation_classes(graph)["c1"] != simulation.bisimulation_classes(graph)["c2"]
+    assert simulation.bisimulation_classes(graph, label_key_fn=first_letter)["c2"] == 2
+    same_letter = lambda a, b: a["label"][0] == b["label"][0]
+    assert simulation.bisimulation_classes(graph, same_letter) == simulation.bisimulation_classes(graph, label_key_fn=first_letter)
+
+
+def test_quotient_graph_preserves_simulation():
+    for seed in range(5):
+        graph = random_labeled_graph(60, 0.03, 2, seed=seed)
+        classes = simulation.bisimulation_classes(graph)
+        assert len(set(classes.values())) < len(graph)
+        # every class is stable: same label and same successor classes
+        for v in graph:
+            for w in graph:
+                if classes[v] == classes[w]:
+                    assert graph.nodes[v] == graph.nodes[w]
+                    assert {classes[x] for x in graph.successors(v)} == {classes[x] for x in graph.successors(w)}
+
+        quotient = simulation.quotient_graph(graph)
+        representative = {classes[v]: v for v in reversed(list(graph))}
+        query = random_labeled_graph(4, 0.4, 2, seed=seed + 10)
+        on_quotient = simulation.get_simulation_inter(query, quotient)
+        expanded = {u: {v for v in graph if representative[classes[v]] in matches} for u, matches in on_quotient.items()}
+        assert expanded == reference_simulation(query, graph)
+
+
+def test_minimize_query_merges_and_drops_edges():
+    query = nx.DiGraph()
+    for node, label in (("q0", "A"), ("q1", "B"), ("q2", "B"), ("q3", "B"), ("q4", "C")):
+        query.add_node(node, label=label)
+    query.add_edges_from([("q0", "q1"), ("q0", "q2"), ("q0", "q3"), ("q3", "q4")])
+
+    # q1 and q2 simulate each other; q3 simulates them, so the edges to them are implied
+    minimized, mapping = simulation.minimize_query(query, return_mapping=True)
+    assert isinstance(minimized, simulation.Graph)
+    assert mapping == {"q0": "q0", "q1": "q1", "q2": "q1", "q3": "q3", "q4": "q4"}
+    reduced = minimized.to_networkx()
+    assert dict(reduced.nodes(data=True)) == {"q0": {"label": "A"}, "q1": {"label": "B"}, "q3": {"label": "B"}, "q4": {"label": "C"}}
+    assert sorted(reduced.edges) == [("q0", "q3"), ("q3", "q4")]
+
+    # with a compare that never matches, even a node does not simulate itself and nothing merges
+    assert len(simulation.minimize_query(query, lambda a, b: False)) == 5
+
+
+def test_minimize_query_preserves_simulation():
+    shrunk = 0
+    for seed in range(20):
+        directed = seed % 2 == 0
+        query = random_labeled_graph(8, 0.3, 2, seed=seed)
+        if not directed:
+            query = query.to_undirected()
+        data = random_labeled_graph(40, 0.08, 2, seed=seed + 100)
+        if not directed:
+            data = data.to_undirected()
+        minimized, mapping = simulation.minimize_query(query, return_mapping=True)
+        assert len(minimized) <= len(query)
+        shrunk += len(minimized) < len(query) or minimized.to_networkx().number_of_edges() < query.number_of_edges()
+        # merging can create self-loops, so both sides use the reference
+        same = lambda a, b: a == b
+        on_minimized = reference_relation_pairs(minimized.to_networkx(), data, same)
+        expanded = {(u, v) for u in query for v in data if (mapping[u], v) in on_minimized}
+        assert expanded == reference_relation_pairs(query, data, same)
+    assert shrunk > 0
+
+
+def test_query_contained_returns_witness():
+    # q1 asks for an A with two B children; q2 for an A with a B child
+    q1 = nx.DiGraph()
+    for node, label in (("a", "A"), ("b1", "B"), ("b2", "B")):
+        q1.add_node(node, label=label)
+    q1.add_edges_from([("a", "b1"), ("a", "b2")])
+    q2 = nx.DiGraph()
+    q2.add_node("x", label="A")
+    q2.add_node("y", label="B")
+    q2.add_edge("x", "y")
+
+    assert simulation.query_contained(q1, q2) == (True, {"x": {"a"}, "y": {"b1", "b2"}})
+    assert simulation.query_contained(q2, q1) == (True, {"a": {"x"}, "b1": {"y"}, "b2": {"y"}})
+    assert simulation.query_contained(q1, q1) == (True, {"a": {"a"}, "b1": {"b1", "b2"}, "b2": {"b1", "b2"}})
+
+    # compare receives the q2 attributes first
+    seen = []
+
+    def compare(attr2, attr1):
+        seen.append((attr2["label"], attr1["label"]))
+        return attr2["label"] == attr1["label"]
+
+    assert simulation.query_contained(q1, q2, compare)[0]
+    assert ("A", "A") in seen and all(pair[0] in "AB" for pair in seen)
+
+    # a q1 node that no q2 node stands for is matched by q1 but never by q2
+    q1.add_node("c", label="C")
+    q1.add_edge("b1", "c")
+    assert simulation.query_contained(q1, q2) == (False, None)
+
+    empty = nx.DiGraph()
+    assert simulation.query_contained(q1, empty) == (False, None)
+    assert simulation.query_contained(empty, q2) == (True, {"x": set(), "y": set()})
+
+
+def test_query_contained_implies_match_containment():
+    contained = 0
+    for seed in range(60):
+        q1 = random_labeled_graph(4, 0.4, 2, seed=seed)
+        q2 = random_labeled_graph(3, 0.4, 2, seed=seed + 1000)
+        is_contained, witness = simulation.query_contained(q1, q2)
+        if not is_contained:
+            assert witness is None
+            continue
+        contained += 1
+        assert all(witness.values())
+        data = random_labeled_graph(30, 0.1, 2, seed=seed + 2000)
+        sim1, sim2 = reference_simulation(q1, data), reference_simulation(q2, data)
+        for u2, nodes in witness.items():
+            assert all(sim1[u1] <= sim2[u2] for u1 in nodes)
+        if not all(sim1.values()):
+            continue
+        # every node and edge q1 matches is matched by q2
+        assert all(sim2.values())
+        assert set().union(*sim1.values()) <= set().union(*sim2.values())
+        for c1, c2 in q1.edges:
+            for v1 in sim1[c1]:
+                for v2 in sim1[c2]:
+                    if data.has_edge(v1, v2):
+                        assert any(v1 in sim2[x] and v2 in sim2[y] for x, y in q2.edges)
+    assert contained > 0